@@ -0,0 +1,291 @@
+//! `#[derive(SbioEvent)]` for the `sbio` crate.
+//!
+//! The derive implements `sbio::SbioPayload` for a `#[repr(C)]` struct whose fields are `i8`,
+//! `u8`, `i16`, `u16`, `i32`, `u32`, `f32` or fixed size arrays of those. The format string is
+//! built from the fields in declaration order, so
+//!
+//! ```ignore
+//! #[derive(SbioEvent)]
+//! #[repr(C)]
+//! #[sbio(name = "motor_status", target = "hmi")]
+//! struct MotorStatus {
+//!     speed: u32,
+//!     temperature: i16,
+//!     flags: [u8; 2],
+//! }
+//! ```
+//!
+//! gets the format `"4u1 speed 2s1 temperature 1u2 flags"` and a size of 8 bytes. The event name
+//! defaults to the struct name and the target defaults to empty (the default target).
+//!
+//! Storyboard reads the payload as packed fields, so the struct must not contain padding. Order
+//! fields from largest to smallest to avoid it; a struct with padding fails to compile.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Lit, LitStr, Type};
+
+#[proc_macro_derive(SbioEvent, attributes(sbio))]
+pub fn derive_sbio_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+// A primitive type that can appear in a format string
+struct Primitive {
+    size: usize,
+    kind: char,
+    ty: &'static str,
+}
+
+const PRIMITIVES: &[Primitive] = &[
+    Primitive {
+        size: 1,
+        kind: 's',
+        ty: "i8",
+    },
+    Primitive {
+        size: 1,
+        kind: 'u',
+        ty: "u8",
+    },
+    Primitive {
+        size: 2,
+        kind: 's',
+        ty: "i16",
+    },
+    Primitive {
+        size: 2,
+        kind: 'u',
+        ty: "u16",
+    },
+    Primitive {
+        size: 4,
+        kind: 's',
+        ty: "i32",
+    },
+    Primitive {
+        size: 4,
+        kind: 'u',
+        ty: "u32",
+    },
+    Primitive {
+        size: 4,
+        kind: 'f',
+        ty: "f32",
+    },
+];
+
+// The element type of a supported field type and its length if it's an array
+fn field_layout(ty: &Type) -> syn::Result<(&'static Primitive, Option<usize>)> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            if let Some(ident) = path.path.get_ident() {
+                if let Some(primitive) = PRIMITIVES.iter().find(|p| ident == p.ty) {
+                    return Ok((primitive, None));
+                }
+            }
+        }
+        Type::Array(array) => {
+            let (primitive, nested) = field_layout(&array.elem)?;
+            if nested.is_none() {
+                if let Expr::Lit(ref expr) = array.len {
+                    if let Lit::Int(ref len) = expr.lit {
+                        let len: usize = len.base10_parse()?;
+                        if len == 0 {
+                            return Err(syn::Error::new(
+                                array.len.span(),
+                                "SbioEvent arrays need at least one element",
+                            ));
+                        }
+                        return Ok((primitive, Some(len)));
+                    }
+                }
+                return Err(syn::Error::new(
+                    array.len.span(),
+                    "SbioEvent array lengths must be integer literals",
+                ));
+            }
+        }
+        _ => {}
+    }
+
+    Err(syn::Error::new(
+        ty.span(),
+        "SbioEvent fields must be i8, u8, i16, u16, i32, u32, f32 or an array of them",
+    ))
+}
+
+// The event name and target from #[sbio(name = "...", target = "...")]
+fn event_attributes(input: &DeriveInput) -> syn::Result<(String, String)> {
+    let mut name = input.ident.to_string();
+    let mut target = String::new();
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("sbio"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else if meta.path.is_ident("target") {
+                target = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `name` or `target`"))
+            }
+        })?;
+    }
+
+    Ok((name, target))
+}
+
+fn has_repr_c(input: &DeriveInput) -> bool {
+    let mut repr_c = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            Ok(())
+        });
+    }
+    repr_c
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "SbioEvent can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "SbioEvent can only be derived for structs",
+            ))
+        }
+    };
+    if !has_repr_c(input) {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "SbioEvent requires #[repr(C)]",
+        ));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "SbioEvent can't be derived for generic structs",
+        ));
+    }
+
+    let (event_name, event_target) = event_attributes(input)?;
+
+    let mut specs = Vec::new();
+    let mut size = 0;
+    let mut encode = Vec::new();
+    let mut decode = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let (primitive, array_len) = field_layout(&field.ty)?;
+        let count = array_len.unwrap_or(1);
+        let element_ty: Type = syn::parse_str(primitive.ty)?;
+        let element_size = primitive.size;
+
+        specs.push(format!(
+            "{}{}{} {}",
+            primitive.size, primitive.kind, count, ident
+        ));
+        size += primitive.size * count;
+
+        if array_len.is_none() {
+            encode.push(quote! {
+                payload.extend_from_slice(&self.#ident.to_ne_bytes());
+            });
+            decode.push(quote! {
+                #ident: {
+                    let mut bytes = [0u8; #element_size];
+                    bytes.copy_from_slice(&payload[offset..offset + #element_size]);
+                    offset += #element_size;
+                    <#element_ty>::from_ne_bytes(bytes)
+                }
+            });
+        } else {
+            encode.push(quote! {
+                for element in self.#ident.iter() {
+                    payload.extend_from_slice(&element.to_ne_bytes());
+                }
+            });
+            decode.push(quote! {
+                #ident: {
+                    let mut value = [<#element_ty>::default(); #count];
+                    for element in value.iter_mut() {
+                        let mut bytes = [0u8; #element_size];
+                        bytes.copy_from_slice(&payload[offset..offset + #element_size]);
+                        offset += #element_size;
+                        *element = <#element_ty>::from_ne_bytes(bytes);
+                    }
+                    value
+                }
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    let format = specs.join(" ");
+    let padding_message = format!(
+        "SbioEvent struct {} contains padding, order its fields from largest to smallest",
+        ident
+    );
+
+    Ok(quote! {
+        const _: () = assert!(
+            ::std::mem::size_of::<#ident>() == #size,
+            #padding_message
+        );
+
+        impl ::sbio::SbioPayload for #ident {
+            const FORMAT: &'static str = #format;
+            const SIZE: usize = #size;
+            const NAME: &'static str = #event_name;
+            const TARGET: &'static str = #event_target;
+
+            fn encode(&self) -> ::std::vec::Vec<u8> {
+                let mut payload = ::std::vec::Vec::with_capacity(#size);
+                #(#encode)*
+                payload
+            }
+
+            #[allow(unused_assignments, unused_mut, unused_variables)]
+            fn decode(payload: &[u8]) -> ::std::result::Result<Self, ::sbio::SbioError> {
+                if payload.len() < #size {
+                    return ::std::result::Result::Err(::sbio::SbioError::PayloadSize {
+                        expected: #size,
+                        actual: payload.len(),
+                    });
+                }
+
+                let mut offset = 0;
+                ::std::result::Result::Ok(#ident {
+                    #(#decode),*
+                })
+            }
+        }
+    })
+}
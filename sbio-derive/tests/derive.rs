@@ -0,0 +1,97 @@
+use sbio::format::Format;
+use sbio::{Sbio, SbioError, SbioEvent, SbioPayload};
+use std::time::Duration;
+
+#[derive(SbioEvent, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+#[sbio(name = "motor_status", target = "hmi")]
+struct MotorStatus {
+    speed: u32,
+    torque: f32,
+    temperature: i16,
+    flags: [u8; 2],
+}
+
+#[derive(SbioEvent, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+struct Single {
+    values: [i8; 1],
+}
+
+#[derive(SbioEvent, Debug, PartialEq)]
+#[repr(C)]
+struct Ping {}
+
+#[test]
+fn layout_test() {
+    assert_eq!(
+        MotorStatus::FORMAT,
+        "4u1 speed 4f1 torque 2s1 temperature 1u2 flags"
+    );
+    assert_eq!(MotorStatus::SIZE, 12);
+    assert_eq!(MotorStatus::NAME, "motor_status");
+    assert_eq!(MotorStatus::TARGET, "hmi");
+
+    let format = Format::parse(MotorStatus::FORMAT).unwrap();
+    assert_eq!(format.byte_size(), Some(MotorStatus::SIZE));
+
+    assert_eq!(Single::FORMAT, "1s1 values");
+    let single = Single { values: [-1] };
+    assert_eq!(Single::decode(&single.encode()), Ok(single));
+
+    assert_eq!(Ping::FORMAT, "");
+    assert_eq!(Ping::SIZE, 0);
+    assert_eq!(Ping::NAME, "Ping");
+    assert_eq!(Ping::TARGET, "");
+}
+
+#[test]
+fn encode_decode_test() {
+    let status = MotorStatus {
+        speed: 1200,
+        torque: 2.5,
+        temperature: -40,
+        flags: [1, 2],
+    };
+
+    let payload = status.encode();
+    assert_eq!(payload.len(), MotorStatus::SIZE);
+    assert_eq!(&payload[..4], &1200u32.to_ne_bytes());
+    assert_eq!(&payload[10..], &[1, 2]);
+    assert_eq!(MotorStatus::decode(&payload), Ok(status));
+
+    assert_eq!(
+        MotorStatus::decode(&payload[..8]),
+        Err(SbioError::PayloadSize {
+            expected: 12,
+            actual: 8
+        })
+    );
+}
+
+#[test]
+fn send_value_test() {
+    let mut sbio = Sbio();
+    let mut rcv = sbio.connect_receive("derive_send_value").unwrap();
+    let mut send = sbio.connect_send("derive_send_value").unwrap();
+
+    let status = MotorStatus {
+        speed: 10,
+        torque: 0.5,
+        temperature: 21,
+        flags: [0, 1],
+    };
+    assert!(send.send_value(&status).is_ok());
+    assert!(send.send_value(&Ping {}).is_ok());
+
+    let event = rcv.receive_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(event.name(), "motor_status");
+    assert_eq!(event.target(), "hmi");
+    assert_eq!(event.format(), MotorStatus::FORMAT);
+    assert_eq!(event.decode::<MotorStatus>(), Ok(status));
+    assert_eq!(*unsafe { event.data::<MotorStatus>() }.unwrap(), status);
+
+    let event = rcv.receive_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(event.name(), "Ping");
+    assert_eq!(event.decode::<Ping>(), Ok(Ping {}));
+}
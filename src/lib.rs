@@ -1,29 +1,158 @@
+#[cfg(feature = "derive")]
+extern crate sbio_derive;
+
 mod sbio;
+pub use sbio::channel::{ChannelName, ChannelNameError, IntoChannelName, MAX_CHANNEL_NAME_LEN};
+pub use sbio::error::{SbioError, SbioErrorCode};
+pub use sbio::format;
+use sbio::format::Format;
+use sbio::fragment::{fragment_format, FRAGMENT_HEADER_SIZE};
+pub use sbio::fragment::{SbioFragmenter, SbioReassembler, FRAGMENT_FORMAT_PREFIX};
+pub use sbio::mdata::{SbioMultiDataEntries, SbioMultiDataEntry, MDATA_EVENT_NAME};
+pub use sbio::payload::SbioPayload;
+pub use sbio::sbio_sys::SBIO_FLAGS;
 use sbio::sbio_sys::*;
-use std::sync::{Arc, Mutex};
-use std::thread::*;
+pub use sbio::value::{SbioValue, SbioValueMap};
+#[cfg(feature = "derive")]
+pub use sbio_derive::SbioEvent;
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::mem::{size_of, size_of_val};
 use std::result::Result;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::*;
+use std::time::{Duration, Instant};
+
+// Accessors for the parts of a serialized event, shared by the types that hold one in `buffer`
+// with its parts located in `header`
+macro_rules! event_accessors {
+    () => {
+        // The event name, with anything that isn't valid UTF-8 replaced. name_cstr gives the raw
+        // bytes and try_name rejects names that aren't valid UTF-8.
+        pub fn name(&self) -> Cow<'_, str> {
+            self.name_cstr().to_string_lossy()
+        }
+
+        pub fn name_cstr(&self) -> &CStr {
+            self.header.name(&self.buffer)
+        }
+
+        pub fn try_name(&self) -> Result<&str, SbioError> {
+            event_str("name", self.name_cstr())
+        }
+
+        // The event target, with anything that isn't valid UTF-8 replaced
+        pub fn target(&self) -> Cow<'_, str> {
+            self.target_cstr().to_string_lossy()
+        }
+
+        pub fn target_cstr(&self) -> &CStr {
+            self.header.target(&self.buffer)
+        }
+
+        pub fn try_target(&self) -> Result<&str, SbioError> {
+            event_str("target", self.target_cstr())
+        }
+
+        // The event format string, with anything that isn't valid UTF-8 replaced
+        pub fn format(&self) -> Cow<'_, str> {
+            self.format_cstr().to_string_lossy()
+        }
+
+        pub fn format_cstr(&self) -> &CStr {
+            self.header.format(&self.buffer)
+        }
+
+        pub fn try_format(&self) -> Result<&str, SbioError> {
+            event_str("format", self.format_cstr())
+        }
+
+        // Get the raw event payload bytes
+        pub fn payload(&self) -> &[u8] {
+            self.header.payload(&self.buffer)
+        }
+
+        // Decode the event payload into field values using the event's own format string
+        pub fn values(&self) -> Result<SbioValueMap, SbioError> {
+            let format = Format::parse(self.try_format()?)?;
+            SbioValueMap::decode(&format, self.payload())
+        }
+
+        // Decode the event payload into a SbioPayload type
+        pub fn decode<T: SbioPayload>(&self) -> Result<T, SbioError> {
+            T::decode(self.payload())
+        }
+    };
+}
 
 pub struct SbioSerializeData {
     buffer: sbio_serialized_data,
+    header: sbio_event_header,
 }
 
 impl SbioSerializeData {
-    pub fn name(&mut self) -> &str {
-        unserialize_event_name(&self.buffer)
+    // Take ownership of a serialized event, locating its parts once up front
+    fn new(buffer: sbio_serialized_data) -> SbioSerializeData {
+        let header = unserialize_header(&buffer);
+        SbioSerializeData { buffer, header }
     }
 
-    pub fn target(&mut self)  -> &str {
-        unserialize_event_target(&self.buffer)
+    event_accessors!();
+
+    /// Interpret the event payload as a T. Fails unless the payload is exactly the size of T, so
+    /// an event of a different shape is rejected, and copies the payload into an aligned value if
+    /// it isn't suitably aligned to be borrowed in place. Prefer `decode`, which checks the
+    /// payload instead of trusting the sender.
+    ///
+    /// # Safety
+    ///
+    /// The payload comes from the sender unchecked, so every bit pattern of its
+    /// `size_of::<T>()` bytes must be a valid T. Types such as `bool`, `char`, enums and anything
+    /// holding a reference don't meet this; plain integers, floats and `#[repr(C)]` structs of
+    /// them do.
+    pub unsafe fn data<T: Copy>(&self) -> Result<Cow<'_, T>, SbioError> {
+        let payload = self.payload();
+        if payload.len() != size_of::<T>() {
+            return Err(SbioError::PayloadSize {
+                expected: size_of::<T>(),
+                actual: payload.len(),
+            });
+        }
+
+        let ptr = payload.as_ptr() as *const T;
+        if ptr.is_aligned() {
+            Ok(Cow::Borrowed(&*ptr))
+        } else {
+            Ok(Cow::Owned(std::ptr::read_unaligned(ptr)))
+        }
     }
 
-    pub fn format(&mut self)  -> &str {
-        unserialize_event_format(&self.buffer)
+    // Render the event payload as a data string, e.g. `4u1:value 20 1s0:name "Crank"`
+    pub fn data_string(&self) -> Result<String, SbioError> {
+        event_data_to_string(self.try_format()?, self.payload())
     }
 
-    pub fn data<T>(&mut self) -> Result<&T, &'static str> {
-        let (_, _, _, data, _) = unserialize(&self.buffer);
-        Ok(data)
+    // Whether the event was sent with send_mdata
+    pub fn is_mdata(&self) -> bool {
+        self.name_cstr().to_bytes() == MDATA_EVENT_NAME.as_bytes()
+    }
+
+    // Iterate over the key/value entries of a multi data event
+    pub fn mdata(&self) -> Result<SbioMultiDataEntries<'_>, SbioError> {
+        if !self.is_mdata() {
+            return Err(SbioError::Unsupported("event isn't a multi data event"));
+        }
+        Ok(SbioMultiDataEntries::new(self.payload()))
+    }
+
+    // Copy the event out of the serialized buffer so it can be kept after the buffer is freed
+    pub fn into_owned(self) -> SbioEvent {
+        SbioEvent {
+            target: self.target().into_owned(),
+            name: self.name().into_owned(),
+            format: self.format().into_owned(),
+            payload: self.payload().to_vec(),
+        }
     }
 }
 
@@ -33,22 +162,348 @@ impl Drop for SbioSerializeData {
     }
 }
 
+// Read an event string as UTF-8, naming the field in the error if it isn't
+fn event_str<'a>(field: &'static str, value: &'a CStr) -> Result<&'a str, SbioError> {
+    value.to_str().map_err(|_| SbioError::InvalidUtf8 { field })
+}
+
+// Work out the payload size of data, checking it against the size described by format when the
+// format has a fixed size
+fn checked_size<T>(format: &str, data: &T) -> Result<u32, SbioError> {
+    let data_size = size_of_val(data);
+    match Format::parse(format)?.byte_size() {
+        Some(size) if size != data_size => Err(SbioError::SizeMismatch { size, data_size }),
+        _ => Ok(data_size as u32),
+    }
+}
+
+// Data manager key/value pairs that are sent together in a single message
+pub struct SbioMultiData {
+    buffer: sbio_serialized_data,
+    len: usize,
+}
+
+impl SbioMultiData {
+    pub fn new() -> SbioMultiData {
+        SbioMultiData {
+            buffer: new_buffer(),
+            len: 0,
+        }
+    }
+
+    // Add a key with an already encoded value. Multi data entries carry a single format spec
+    // without a field name, e.g. "4u1" or "1s0".
+    pub fn add_bytes(
+        &mut self,
+        key: &str,
+        format: &str,
+        data: &[u8],
+    ) -> Result<&mut SbioMultiData, SbioError> {
+        add_mdata(&mut self.buffer, key, format, data)?;
+        self.len += 1;
+        Ok(self)
+    }
+
+    // Add a key with a single value, using the format spec that matches its type
+    pub fn add_value(
+        &mut self,
+        key: &str,
+        value: &SbioValue,
+    ) -> Result<&mut SbioMultiData, SbioError> {
+        let (spec, data) = value.encode_single()?;
+        self.add_bytes(key, &spec, &data)
+    }
+
+    // The number of keys added so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for SbioMultiData {
+    fn default() -> SbioMultiData {
+        SbioMultiData::new()
+    }
+}
+
+impl Drop for SbioMultiData {
+    fn drop(&mut self) {
+        free_buffer(&self.buffer)
+    }
+}
+
+// A serialized event buffer that is allocated once and reused for many events
+pub struct SbioBuffer {
+    buffer: sbio_serialized_data,
+    header: sbio_event_header,
+}
+
+impl SbioBuffer {
+    // An empty buffer, allocated by the first event serialized into it
+    pub fn new() -> SbioBuffer {
+        SbioBuffer {
+            buffer: new_buffer(),
+            header: Default::default(),
+        }
+    }
+
+    // A buffer with room for a serialized message of capacity bytes
+    pub fn with_capacity(capacity: usize) -> Result<SbioBuffer, SbioError> {
+        let mut buffer = SbioBuffer::new();
+        buffer.reserve(capacity)?;
+        Ok(buffer)
+    }
+
+    // Make sure the buffer can hold a serialized message of capacity bytes
+    pub fn reserve(&mut self, capacity: usize) -> Result<(), SbioError> {
+        if capacity > self.capacity() || self.capacity() == 0 {
+            size_buffer(&mut self.buffer, capacity)?;
+        }
+        Ok(())
+    }
+
+    // The number of bytes allocated for the buffer
+    pub fn capacity(&self) -> usize {
+        buffer_capacity(&self.buffer)
+    }
+
+    // The number of bytes of serialized event data in the buffer
+    pub fn len(&self) -> usize {
+        buffer_len(&self.buffer)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Discard the buffered event, keeping the allocation for reuse
+    pub fn clear(&mut self) {
+        zero_buffer(&mut self.buffer);
+        self.header = Default::default();
+    }
+
+    // Locate the parts of the buffered event after its content changes. Called on failure too,
+    // since greio may have touched the buffer before failing.
+    fn update_header<R>(&mut self, result: R) -> R {
+        self.header = unserialize_header(&self.buffer);
+        result
+    }
+
+    // The event name, target and format are empty until an event is serialized or received
+    event_accessors!();
+
+    // Serialize an event into the buffer, replacing its previous content
+    pub fn serialize<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+        size: u32,
+    ) -> Result<(), SbioError> {
+        let result = serialize_into(&mut self.buffer, target, name, format, data, size);
+        self.update_header(result)
+    }
+
+    // Serialize an event from an already encoded payload into the buffer
+    pub fn serialize_bytes(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: &[u8],
+    ) -> Result<(), SbioError> {
+        let result = serialize_bytes_into(&mut self.buffer, target, name, format, data);
+        self.update_header(result)
+    }
+
+    // Serialize an event from strings that are passed to greio as is, for example to forward a
+    // received event whose name or target isn't valid UTF-8
+    pub fn serialize_cstr(
+        &mut self,
+        target: &CStr,
+        name: &CStr,
+        format: &CStr,
+        data: &[u8],
+    ) -> Result<(), SbioError> {
+        let result = serialize_cstr_into(&mut self.buffer, target, name, format, data);
+        self.update_header(result)
+    }
+
+    // Serialize an event built from field values laid out as described by format
+    pub fn serialize_values(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        values: &SbioValueMap,
+    ) -> Result<(), SbioError> {
+        let payload = values.encode(&Format::parse(format)?)?;
+        self.serialize_bytes(target, name, format, &payload)
+    }
+
+    // Serialize a value using the event name, target and format from its SbioPayload impl
+    pub fn serialize_value<T: SbioPayload>(&mut self, value: &T) -> Result<(), SbioError> {
+        self.serialize_bytes(T::TARGET, T::NAME, T::FORMAT, &value.encode())
+    }
+}
+
+impl Default for SbioBuffer {
+    fn default() -> SbioBuffer {
+        SbioBuffer::new()
+    }
+}
+
+impl Drop for SbioBuffer {
+    fn drop(&mut self) {
+        free_buffer(&self.buffer)
+    }
+}
+
+// An owned copy of a received event
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SbioEvent {
+    pub target: String,
+    pub name: String,
+    pub format: String,
+    pub payload: Vec<u8>,
+}
+
 struct SbioConnectionData {
     channel_handle: sbio_channel_handle,
     channel_open: bool,
+    listening: bool,
+}
+
+impl SbioConnectionData {
+    // Receive a serialized event, mapping an empty queue to None
+    fn try_receive(&self) -> Result<Option<SbioSerializeData>, SbioError> {
+        match receive(&self.channel_handle) {
+            Ok(buffer) => Ok(Some(SbioSerializeData::new(buffer))),
+            Err(ref err) if err.code() == Some(SbioErrorCode::QueueEmpty) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+// The most original event bytes a fragment can carry and still fit within max
+fn fragment_data_limit(target: &str, name: &str, max: usize) -> Result<usize, SbioError> {
+    // The fragment's count field never needs more digits than max
+    let overhead = message_overhead(target, name, &fragment_format(max))? + FRAGMENT_HEADER_SIZE;
+    if overhead >= max {
+        return Err(SbioError::MessageTooLarge {
+            size: overhead + 1,
+            max,
+        });
+    }
+    Ok(max - overhead)
+}
+
+// The size of a serialized event on top of its payload, measured by serializing it without one
+fn message_overhead(target: &str, name: &str, format: &str) -> Result<usize, SbioError> {
+    let mut buffer = SbioBuffer::new();
+    buffer.serialize_bytes(target, name, format, &[])?;
+    Ok(buffer.len())
+}
+
+// Map the errors greio uses for a channel that already has a reader to ChannelInUse. EEXIST is
+// what an implementation that creates the exclusive queue with O_EXCL reports. Access stays a
+// greio error, it means permission was denied rather than that the channel is taken.
+fn exclusive_open_error(channel_name: &str, err: SbioError) -> SbioError {
+    let in_use = err.sys_code() == Some(libc::EBUSY) || err.sys_code() == Some(libc::EEXIST);
+
+    if in_use {
+        SbioError::ChannelInUse {
+            channel: String::from(channel_name),
+        }
+    } else {
+        err
+    }
+}
+
+fn check_message_size(size: usize, max_message: Option<usize>) -> Result<(), SbioError> {
+    match max_message {
+        Some(max) if size > max => Err(SbioError::MessageTooLarge { size, max }),
+        _ => Ok(()),
+    }
+}
+
+// Send a serialized message, rejecting one that's bigger than the channel limit before handing it
+// to greio, which would only report a generic failure
+fn send_checked(
+    thread_data: &Mutex<SbioConnectionData>,
+    max_message: Option<usize>,
+    buffer: &sbio_serialized_data,
+) -> Result<i32, SbioError> {
+    check_message_size(buffer_len(buffer), max_message)?;
+    let thread_data = lock_data(thread_data);
+    if !thread_data.channel_open {
+        return Err(SbioError::Closed("channel"));
+    }
+    send(&thread_data.channel_handle, buffer)
+}
+
+// Lock the connection data. A panic while the lock is held can't leave the data inconsistent, so
+// a poisoned lock is used as is rather than propagating the panic.
+fn lock_data(thread_data: &Mutex<SbioConnectionData>) -> MutexGuard<'_, SbioConnectionData> {
+    thread_data
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Bounds for the sleep between polls in receive_timeout and the listener thread
+const RECEIVE_POLL_MIN: Duration = Duration::from_micros(100);
+const RECEIVE_POLL_MAX: Duration = Duration::from_millis(10);
+
+// Receive loop run on the listener thread until stop_listener clears the listening flag or the
+// channel reports an error
+fn listen<F>(thread_data: Arc<Mutex<SbioConnectionData>>, mut callback: F) -> Result<(), SbioError>
+where
+    F: FnMut(SbioSerializeData),
+{
+    let mut interval = RECEIVE_POLL_MIN;
+    loop {
+        let event = {
+            let thread_data = lock_data(&thread_data);
+            if !thread_data.listening || !thread_data.channel_open {
+                return Ok(());
+            }
+            thread_data.try_receive()?
+        };
+
+        match event {
+            Some(event) => {
+                callback(event);
+                interval = RECEIVE_POLL_MIN;
+            }
+            None => {
+                sleep(interval);
+                interval = std::cmp::min(interval * 2, RECEIVE_POLL_MAX);
+            }
+        }
+    }
 }
 
-#[allow(dead_code)]
 pub struct SbioConnection {
     flags: u32,
-    thread_handle: Option<JoinHandle<()>>,
+    // Reused by the send_* helpers so sending doesn't allocate per event
+    send_buffer: SbioBuffer,
+    // The channel's message size limit, if greio reports one
+    max_message: Option<usize>,
+    thread_handle: Option<JoinHandle<Result<(), SbioError>>>,
     thread_data: Arc<Mutex<SbioConnectionData>>,
 }
 
 impl SbioConnection {
-    // Close the SBIO channel and free the handle
+    // Close the SBIO channel and free the handle, stopping the listener thread first
     pub fn close(&mut self) {
-        let mut thread_data = self.thread_data.lock().unwrap();
+        let _ = self.stop_listener();
+
+        let mut thread_data = lock_data(&self.thread_data);
         if thread_data.channel_open {
             let sbio_channel_handle = &mut thread_data.channel_handle;
             close(sbio_channel_handle);
@@ -56,13 +511,30 @@ impl SbioConnection {
         }
     }
 
+    // The largest message the channel accepts, or None if the channel is unlimited or the greio
+    // implementation doesn't report a limit (the SysV queue build used for the tests doesn't)
+    pub fn max_message_size(&self) -> Option<usize> {
+        self.max_message
+    }
+
+    // Raise the channel's message size limit to fit the capacity of buffer. Not every greio
+    // implementation supports this, those that don't return Unsupported.
+    pub fn grow_to(&mut self, buffer: &SbioBuffer) -> Result<(), SbioError> {
+        let thread_data = lock_data(&self.thread_data);
+        grow_buffer(&thread_data.channel_handle, &buffer.buffer)?;
+        self.max_message = max_message(&thread_data.channel_handle);
+
+        Ok(())
+    }
+
+    // Send a serialized message on the channel, checking it against the channel limit first
+    fn send_serialized(&self, buffer: &sbio_serialized_data) -> Result<i32, SbioError> {
+        send_checked(&self.thread_data, self.max_message, buffer)
+    }
+
     // Send a serialized event
-    pub fn send_serialized_event(
-        &mut self,
-        event: &SbioSerializeData,
-    ) -> Result<i32, &'static str> {
-        let thread_data = self.thread_data.lock().unwrap();
-        send(&thread_data.channel_handle, &event.buffer)
+    pub fn send_serialized_event(&mut self, event: &SbioSerializeData) -> Result<i32, SbioError> {
+        self.send_serialized(&event.buffer)
     }
 
     // Send a event with the event target, name, format, data, and size
@@ -73,28 +545,252 @@ impl SbioConnection {
         format: &str,
         data: T,
         size: u32,
-    ) -> Result<i32, &'static str> {
-        let thread_data = self.thread_data.lock().unwrap();
-        let event = match serialize(target, name, format, data, size) {
-            Ok(event) => event,
-            Err(err) => panic!("Problem sending event: {:?}", err),
+    ) -> Result<i32, SbioError> {
+        self.send_buffer
+            .serialize(target, name, format, data, size)?;
+
+        self.send_serialized(&self.send_buffer.buffer)
+    }
+
+    // Send a event with the event target, name, format, and data, taking the size from the data
+    // and rejecting data that doesn't match the size described by the format
+    pub fn send_event_checked<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+    ) -> Result<i32, SbioError> {
+        let size = checked_size(format, &data)?;
+        self.send_event(target, name, format, data, size)
+    }
+
+    // Send a event built from field values laid out as described by format
+    pub fn send_values(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        values: &SbioValueMap,
+    ) -> Result<i32, SbioError> {
+        self.send_buffer
+            .serialize_values(target, name, format, values)?;
+
+        self.send_serialized(&self.send_buffer.buffer)
+    }
+
+    // Send a value using the event name, target and format from its SbioPayload impl
+    pub fn send_value<T: SbioPayload>(&mut self, value: &T) -> Result<i32, SbioError> {
+        self.send_buffer.serialize_value(value)?;
+
+        self.send_serialized(&self.send_buffer.buffer)
+    }
+
+    // Send an owned event, for example one returned by receive_reassembled
+    pub fn send_owned_event(&mut self, event: &SbioEvent) -> Result<i32, SbioError> {
+        self.send_buffer.serialize_bytes(
+            &event.target,
+            &event.name,
+            &event.format,
+            &event.payload,
+        )?;
+
+        self.send_serialized(&self.send_buffer.buffer)
+    }
+
+    // Send an event as a sequence of fragment events, returning the number of events sent. The
+    // receiving side rebuilds the event with receive_reassembled. An event that fits within the
+    // channel limit is sent as is. Fragments are sized to fit the limit, or the fragmenter's
+    // fragment size if that's smaller or the channel doesn't report a limit.
+    pub fn send_fragmented(
+        &mut self,
+        fragmenter: &mut SbioFragmenter,
+        target: &str,
+        name: &str,
+        format: &str,
+        payload: &[u8],
+    ) -> Result<usize, SbioError> {
+        // Only events that are sent as is go through the send buffer, so an oversized payload
+        // never grows it
+        let fits = match self.max_message {
+            Some(max) => {
+                message_overhead(target, name, format)?.saturating_add(payload.len()) <= max
+            }
+            None => format.len() + 1 + payload.len() <= fragmenter.fragment_size(),
+        };
+        if fits {
+            self.send_buffer
+                .serialize_bytes(target, name, format, payload)?;
+            self.send_serialized(&self.send_buffer.buffer)?;
+            return Ok(1);
+        }
+
+        let fragment_size = match self.max_message {
+            Some(max) => fragment_data_limit(target, name, max)?.min(fragmenter.fragment_size()),
+            None => fragmenter.fragment_size(),
         };
+        let fragments = fragmenter.fragments_of_size(format, payload, fragment_size);
+        for (fragment_format, fragment) in fragments.iter() {
+            self.send_buffer
+                .serialize_bytes(target, name, fragment_format, fragment)?;
+            self.send_serialized(&self.send_buffer.buffer)?;
+        }
+
+        Ok(fragments.len())
+    }
+
+    // Send the event held in a buffer
+    pub fn send_buffer(&mut self, buffer: &SbioBuffer) -> Result<i32, SbioError> {
+        self.send_serialized(&buffer.buffer)
+    }
 
-        let ret = send(&thread_data.channel_handle, &event);
-        free_buffer(&event);
+    // Send data manager key/value pairs in a single message. Nothing is sent for an empty set.
+    pub fn send_mdata(&mut self, mdata: &SbioMultiData) -> Result<i32, SbioError> {
+        if mdata.is_empty() {
+            return Ok(0);
+        }
 
-        ret
+        check_message_size(buffer_len(&mdata.buffer), self.max_message)?;
+        let thread_data = lock_data(&self.thread_data);
+        send_mdata(&thread_data.channel_handle, &mdata.buffer)
     }
 
     // Receive a serialized event
-    pub fn receive(&mut self) -> Result<SbioSerializeData, &'static str> {
-        let thread_data = self.thread_data.lock().unwrap();
-        let buffer = match receive(&thread_data.channel_handle) {
-            Ok(buffer) => buffer,
-            Err(err) => return Err(err),
+    pub fn receive(&mut self) -> Result<SbioSerializeData, SbioError> {
+        let thread_data = lock_data(&self.thread_data);
+        let buffer = receive(&thread_data.channel_handle)?;
+
+        Ok(SbioSerializeData::new(buffer))
+    }
+
+    // Receive an event into a buffer, reusing its allocation, and return the message size
+    pub fn receive_into(&mut self, buffer: &mut SbioBuffer) -> Result<usize, SbioError> {
+        let thread_data = lock_data(&self.thread_data);
+        let result = receive_into(&thread_data.channel_handle, &mut buffer.buffer);
+        let size = buffer.update_header(result)?;
+
+        Ok(size as usize)
+    }
+
+    // Receive a serialized event, returning None if a non-blocking channel has nothing queued
+    pub fn try_receive(&mut self) -> Result<Option<SbioSerializeData>, SbioError> {
+        let thread_data = lock_data(&self.thread_data);
+        thread_data.try_receive()
+    }
+
+    // Receive a serialized event, waiting up to timeout for one to arrive. The channel must be
+    // non-blocking, it is polled with a backoff between attempts until the deadline passes.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<SbioSerializeData, SbioError> {
+        if self.flags & SBIO_FLAGS::NONBLOCK.as_u32() == 0 {
+            return Err(SbioError::Unsupported(
+                "receive_timeout requires a non-blocking channel",
+            ));
+        }
+
+        // A timeout too large to represent as a deadline waits forever
+        let deadline = Instant::now().checked_add(timeout);
+        let mut interval = RECEIVE_POLL_MIN;
+        loop {
+            if let Some(event) = self.try_receive()? {
+                return Ok(event);
+            }
+
+            let now = Instant::now();
+            let remaining = match deadline {
+                Some(deadline) if now >= deadline => {
+                    return Err(SbioError::timeout("Timed out waiting for event"))
+                }
+                Some(deadline) => deadline - now,
+                None => interval,
+            };
+
+            sleep(std::cmp::min(interval, remaining));
+            interval = std::cmp::min(interval * 2, RECEIVE_POLL_MAX);
+        }
+    }
+
+    // Receive the next complete event, rebuilding fragmented events sent with send_fragmented.
+    // Ordinary events are returned as they arrive. On a non-blocking channel this waits up to
+    // the reassembler's timeout for each event and reports a transfer that stalls.
+    pub fn receive_reassembled(
+        &mut self,
+        reassembler: &mut SbioReassembler,
+    ) -> Result<SbioEvent, SbioError> {
+        loop {
+            // Stalled transfers are reported before receiving, so no event is dropped for them
+            reassembler.expire()?;
+            let result = if self.flags & SBIO_FLAGS::NONBLOCK.as_u32() == 0 {
+                self.receive()
+            } else {
+                self.receive_timeout(reassembler.timeout())
+            };
+            let event = match result {
+                Ok(event) => event.into_owned(),
+                Err(err) => {
+                    reassembler.expire()?;
+                    return Err(err);
+                }
+            };
+
+            if let Some(event) = reassembler.push(event)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    // Start a listener thread that receives events and passes each one to callback. The channel
+    // must be non-blocking and only one listener can run at a time. The listener runs until
+    // stop_listener or close is called, or until a receive fails.
+    pub fn on_event<F>(&mut self, callback: F) -> Result<(), SbioError>
+    where
+        F: FnMut(SbioSerializeData) + Send + 'static,
+    {
+        if self.flags & SBIO_FLAGS::NONBLOCK.as_u32() == 0 {
+            return Err(SbioError::Unsupported(
+                "on_event requires a non-blocking channel",
+            ));
+        }
+        if self.thread_handle.is_some() {
+            return Err(SbioError::Unsupported("a listener is already running"));
+        }
+
+        lock_data(&self.thread_data).listening = true;
+        let thread_data = Arc::clone(&self.thread_data);
+        let spawned = Builder::new()
+            .name(String::from("sbio-listener"))
+            .spawn(move || listen(thread_data, callback));
+        match spawned {
+            Ok(thread_handle) => self.thread_handle = Some(thread_handle),
+            Err(err) => {
+                lock_data(&self.thread_data).listening = false;
+                return Err(SbioError::from_io("Couldn't start listener thread", err));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Stop the listener thread and wait for it to exit. Returns the receive error that ended the
+    // listener early, if there was one.
+    pub fn stop_listener(&mut self) -> Result<(), SbioError> {
+        let thread_handle = match self.thread_handle.take() {
+            Some(thread_handle) => thread_handle,
+            None => return Ok(()),
         };
 
-        Ok(SbioSerializeData { buffer })
+        lock_data(&self.thread_data).listening = false;
+        match thread_handle.join() {
+            Ok(result) => result,
+            Err(_) => Err(SbioError::ListenerPanicked),
+        }
+    }
+
+    // Check whether a listener thread is running
+    pub fn is_listening(&self) -> bool {
+        match self.thread_handle {
+            Some(ref thread_handle) => !thread_handle.is_finished(),
+            None => false,
+        }
     }
 }
 
@@ -104,54 +800,485 @@ impl Drop for SbioConnection {
     }
 }
 
+// Write-only end of a channel, opened with SBIO_FLAGS::WRONLY
+pub struct SbioSender {
+    // Declared before connection so the sink's send thread is stopped before the connection closes
+    #[cfg(feature = "tokio")]
+    sink: sbio::stream::SbioSinkState,
+    connection: SbioConnection,
+}
+
+impl SbioSender {
+    fn new(connection: SbioConnection) -> SbioSender {
+        SbioSender {
+            #[cfg(feature = "tokio")]
+            sink: Default::default(),
+            connection,
+        }
+    }
+
+    // Close the SBIO channel and free the handle. With the tokio feature, events still queued
+    // by the sink are discarded, flush it first to send them.
+    pub fn close(&mut self) {
+        #[cfg(feature = "tokio")]
+        self.stop_sink();
+        self.connection.close()
+    }
+
+    // Send a serialized event
+    pub fn send_serialized_event(&mut self, event: &SbioSerializeData) -> Result<i32, SbioError> {
+        self.connection.send_serialized_event(event)
+    }
+
+    // Send a event with the event target, name, format, data, and size
+    pub fn send_event<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+        size: u32,
+    ) -> Result<i32, SbioError> {
+        self.connection.send_event(target, name, format, data, size)
+    }
+
+    // Send a event with the event target, name, format, and data, taking the size from the data
+    pub fn send_event_checked<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+    ) -> Result<i32, SbioError> {
+        self.connection
+            .send_event_checked(target, name, format, data)
+    }
+
+    // Send a event built from field values laid out as described by format
+    pub fn send_values(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        values: &SbioValueMap,
+    ) -> Result<i32, SbioError> {
+        self.connection.send_values(target, name, format, values)
+    }
+
+    // Send a value using the event name, target and format from its SbioPayload impl
+    pub fn send_value<T: SbioPayload>(&mut self, value: &T) -> Result<i32, SbioError> {
+        self.connection.send_value(value)
+    }
+
+    // Send an owned event, for example one returned by receive_reassembled
+    pub fn send_owned_event(&mut self, event: &SbioEvent) -> Result<i32, SbioError> {
+        self.connection.send_owned_event(event)
+    }
+
+    // Send the event held in a buffer
+    pub fn send_buffer(&mut self, buffer: &SbioBuffer) -> Result<i32, SbioError> {
+        self.connection.send_buffer(buffer)
+    }
+
+    // Send an event as a sequence of fragment events sized to fit the channel, returning the
+    // number of events sent
+    pub fn send_fragmented(
+        &mut self,
+        fragmenter: &mut SbioFragmenter,
+        target: &str,
+        name: &str,
+        format: &str,
+        payload: &[u8],
+    ) -> Result<usize, SbioError> {
+        self.connection
+            .send_fragmented(fragmenter, target, name, format, payload)
+    }
+
+    // The largest message the channel accepts, if the greio implementation reports a limit
+    pub fn max_message_size(&self) -> Option<usize> {
+        self.connection.max_message_size()
+    }
+
+    // Raise the channel's message size limit to fit the capacity of buffer
+    pub fn grow_to(&mut self, buffer: &SbioBuffer) -> Result<(), SbioError> {
+        self.connection.grow_to(buffer)
+    }
+
+    // Send data manager key/value pairs in a single message
+    pub fn send_mdata(&mut self, mdata: &SbioMultiData) -> Result<i32, SbioError> {
+        self.connection.send_mdata(mdata)
+    }
+}
+
+// Read-only end of a channel, opened with SBIO_FLAGS::RDONLY or, for an exclusive reader,
+// SBIO_FLAGS::XRDONLY, either blocking or with SBIO_FLAGS::NONBLOCK. Sbio::connect_receive
+// opens a non-blocking shared reader, SbioConnectionBuilder::receiver allows any of these.
+pub struct SbioReceiver {
+    // Declared before connection so the stream's channel is dropped first, which unblocks the
+    // listener thread before closing the connection joins it
+    #[cfg(feature = "tokio")]
+    stream: sbio::stream::SbioStreamState,
+    connection: SbioConnection,
+}
+
+impl SbioReceiver {
+    fn new(connection: SbioConnection) -> SbioReceiver {
+        SbioReceiver {
+            #[cfg(feature = "tokio")]
+            stream: Default::default(),
+            connection,
+        }
+    }
+
+    // Close the SBIO channel and free the handle, stopping the listener thread first
+    pub fn close(&mut self) {
+        #[cfg(feature = "tokio")]
+        self.stop_stream();
+        self.connection.close()
+    }
+
+    // Receive a serialized event
+    pub fn receive(&mut self) -> Result<SbioSerializeData, SbioError> {
+        self.connection.receive()
+    }
+
+    // Receive a serialized event, returning None if nothing is queued
+    pub fn try_receive(&mut self) -> Result<Option<SbioSerializeData>, SbioError> {
+        self.connection.try_receive()
+    }
+
+    // Receive an event into a buffer, reusing its allocation, and return the message size
+    pub fn receive_into(&mut self, buffer: &mut SbioBuffer) -> Result<usize, SbioError> {
+        self.connection.receive_into(buffer)
+    }
+
+    // Receive the next complete event, rebuilding fragmented events
+    pub fn receive_reassembled(
+        &mut self,
+        reassembler: &mut SbioReassembler,
+    ) -> Result<SbioEvent, SbioError> {
+        self.connection.receive_reassembled(reassembler)
+    }
+
+    // Receive a serialized event, waiting up to timeout for one to arrive
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<SbioSerializeData, SbioError> {
+        self.connection.receive_timeout(timeout)
+    }
+
+    // Start a listener thread that passes each received event to callback
+    pub fn on_event<F>(&mut self, callback: F) -> Result<(), SbioError>
+    where
+        F: FnMut(SbioSerializeData) + Send + 'static,
+    {
+        self.connection.on_event(callback)
+    }
+
+    // Stop the listener thread and wait for it to exit. With the tokio feature this also stops
+    // the stream, which starts listening again on its next poll.
+    pub fn stop_listener(&mut self) -> Result<(), SbioError> {
+        #[cfg(feature = "tokio")]
+        self.stop_stream();
+        self.connection.stop_listener()
+    }
+
+    // Check whether a listener thread is running
+    pub fn is_listening(&self) -> bool {
+        self.connection.is_listening()
+    }
+}
+
+// Options for opening a channel. A new builder opens a blocking, shared reader.
+#[derive(Clone, Debug)]
+pub struct SbioConnectionBuilder {
+    channel_name: ChannelName,
+    flags: SBIO_FLAGS,
+    send_buffer_capacity: usize,
+    message_size_hint: Option<usize>,
+}
+
+impl SbioConnectionBuilder {
+    pub fn new(channel_name: ChannelName) -> SbioConnectionBuilder {
+        SbioConnectionBuilder {
+            channel_name,
+            flags: SBIO_FLAGS::RDONLY,
+            send_buffer_capacity: 0,
+            message_size_hint: None,
+        }
+    }
+
+    fn mode(mut self, mode: SBIO_FLAGS) -> SbioConnectionBuilder {
+        self.flags.remove(SBIO_FLAGS::XRDONLY | SBIO_FLAGS::WRONLY);
+        self.flags.insert(mode);
+        self
+    }
+
+    // Open the channel for reading, sharing it with any other readers
+    pub fn read(self) -> SbioConnectionBuilder {
+        self.mode(SBIO_FLAGS::RDONLY)
+    }
+
+    // Open the channel for reading as its only reader, see Sbio::connect_exclusive_receive
+    pub fn exclusive_read(self) -> SbioConnectionBuilder {
+        self.mode(SBIO_FLAGS::XRDONLY)
+    }
+
+    // Open the channel for writing. The channel must already have a reader.
+    pub fn write(self) -> SbioConnectionBuilder {
+        self.mode(SBIO_FLAGS::WRONLY)
+    }
+
+    // Return QueueEmpty/QueueFull errors instead of blocking. Required for receive_timeout and
+    // on_event.
+    pub fn nonblocking(mut self, nonblocking: bool) -> SbioConnectionBuilder {
+        self.flags.set(SBIO_FLAGS::NONBLOCK, nonblocking);
+        self
+    }
+
+    // Use an exact combination of flags, replacing the mode and blocking options. Flags that
+    // combine XRDONLY and WRONLY are rejected when the channel is opened.
+    pub fn with_flags(mut self, flags: SBIO_FLAGS) -> SbioConnectionBuilder {
+        self.flags = flags;
+        self
+    }
+
+    // Allocate the buffer reused by the send_* methods up front, so the first sends don't
+    // have to grow it
+    pub fn send_buffer_capacity(mut self, capacity: usize) -> SbioConnectionBuilder {
+        self.send_buffer_capacity = capacity;
+        self
+    }
+
+    // Ask greio to raise the channel's message size limit to at least size bytes. This is only
+    // a hint: it's ignored by greio implementations that can't change the limit.
+    pub fn message_size_hint(mut self, size: usize) -> SbioConnectionBuilder {
+        self.message_size_hint = Some(size);
+        self
+    }
+
+    pub fn flags(&self) -> SBIO_FLAGS {
+        self.flags
+    }
+
+    // Open the channel
+    pub fn connect(&self) -> Result<SbioConnection, SbioError> {
+        if self
+            .flags
+            .contains(SBIO_FLAGS::XRDONLY | SBIO_FLAGS::WRONLY)
+        {
+            return Err(SbioError::InvalidFlags(self.flags));
+        }
+
+        let handle = match open(self.channel_name.as_str(), self.flags) {
+            Ok(handle) => handle,
+            Err(err) if self.flags.contains(SBIO_FLAGS::XRDONLY) => {
+                return Err(exclusive_open_error(self.channel_name.as_str(), err))
+            }
+            Err(err) => return Err(err),
+        };
+        let max_message = max_message(&handle);
+
+        let connection_data = SbioConnectionData {
+            channel_handle: handle,
+            channel_open: true,
+            listening: false,
+        };
+
+        let mut connection = SbioConnection {
+            flags: self.flags.as_u32(),
+            send_buffer: SbioBuffer::new(),
+            max_message,
+            thread_handle: None,
+            thread_data: Arc::new(Mutex::new(connection_data)),
+        };
+
+        if self.send_buffer_capacity > 0 {
+            connection.send_buffer.reserve(self.send_buffer_capacity)?;
+        }
+        if let Some(size) = self.message_size_hint {
+            if max_message.is_some_and(|max| max < size) {
+                let buffer = SbioBuffer::with_capacity(size)?;
+                match connection.grow_to(&buffer) {
+                    Ok(()) | Err(SbioError::Unsupported(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(connection)
+    }
+
+    // Open the channel as a SbioSender, which needs the builder to be in write mode
+    pub fn sender(&self) -> Result<SbioSender, SbioError> {
+        if !self.flags.contains(SBIO_FLAGS::WRONLY) {
+            return Err(SbioError::Unsupported(
+                "a sender needs a channel opened for writing",
+            ));
+        }
+
+        let connection = self.connect()?;
+        Ok(SbioSender::new(connection))
+    }
+
+    // Open the channel as a SbioReceiver, which needs the builder to be in a read mode
+    pub fn receiver(&self) -> Result<SbioReceiver, SbioError> {
+        if self.flags.contains(SBIO_FLAGS::WRONLY) {
+            return Err(SbioError::Unsupported(
+                "a receiver needs a channel opened for reading",
+            ));
+        }
+
+        let connection = self.connect()?;
+        Ok(SbioReceiver::new(connection))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Sbio();
 
-impl Sbio {
-    pub fn connect(
+impl Sbio {
+    // Start building a connection to channel_name with explicit options
+    pub fn connection<N: IntoChannelName>(
+        &mut self,
+        channel_name: N,
+    ) -> Result<SbioConnectionBuilder, SbioError> {
+        Ok(SbioConnectionBuilder::new(
+            channel_name.into_channel_name()?,
+        ))
+    }
+
+    pub fn connect<N: IntoChannelName>(
+        &mut self,
+        channel_name: N,
+        flags: SBIO_FLAGS,
+    ) -> Result<SbioConnection, SbioError> {
+        self.connection(channel_name)?.with_flags(flags).connect()
+    }
+
+    // Shorthand for a blocking writer
+    pub fn connect_send<N: IntoChannelName>(
+        &mut self,
+        channel_name: N,
+    ) -> Result<SbioSender, SbioError> {
+        self.connection(channel_name)?.write().sender()
+    }
+
+    // Shorthand for a non-blocking shared reader
+    pub fn connect_receive<N: IntoChannelName>(
+        &mut self,
+        channel_name: N,
+    ) -> Result<SbioReceiver, SbioError> {
+        self.connection(channel_name)?
+            .read()
+            .nonblocking(true)
+            .receiver()
+    }
+
+    // Shorthand for a non-blocking exclusive reader, the only reader of the channel
+    // (SBIO_FLAGS::XRDONLY) so no other process can consume its events. Fails with
+    // SbioError::ChannelInUse when the greio implementation reports that another reader already
+    // has the channel open. Exclusivity is enforced by greio, and not every implementation does
+    // so: the SysV queue build used for the tests accepts any number of readers.
+    pub fn connect_exclusive_receive<N: IntoChannelName>(
+        &mut self,
+        channel_name: N,
+    ) -> Result<SbioReceiver, SbioError> {
+        self.connection(channel_name)?
+            .exclusive_read()
+            .nonblocking(true)
+            .receiver()
+    }
+
+    // Open a sender on send_channel and a receiver on receive_channel, for talking to a peer
+    // that uses a channel per direction. As with connect_send, the peer must already have
+    // send_channel open for reading. Both names are validated before either channel is opened.
+    pub fn connect_duplex<S: IntoChannelName, R: IntoChannelName>(
+        &mut self,
+        send_channel: S,
+        receive_channel: R,
+    ) -> Result<(SbioSender, SbioReceiver), SbioError> {
+        let send_channel = send_channel.into_channel_name()?;
+        let receive_channel = receive_channel.into_channel_name()?;
+        let receiver = self.connect_receive(receive_channel)?;
+        let sender = self.connect_send(send_channel)?;
+        Ok((sender, receiver))
+    }
+
+    pub fn serialize<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+        size: u32,
+    ) -> Result<SbioSerializeData, SbioError> {
+        let buffer = serialize(target, name, format, data, size)?;
+
+        Ok(SbioSerializeData::new(buffer))
+    }
+
+    // Serialize an event taking the size from the data and rejecting data that doesn't match the
+    // size described by the format
+    pub fn serialize_checked<T>(
+        &mut self,
+        target: &str,
+        name: &str,
+        format: &str,
+        data: T,
+    ) -> Result<SbioSerializeData, SbioError> {
+        let size = checked_size(format, &data)?;
+        self.serialize(target, name, format, data, size)
+    }
+
+    // Serialize an event built from field values laid out as described by format
+    pub fn serialize_values(
         &mut self,
-        channel_name: &str,
-        flags: SBIO_FLAGS,
-    ) -> Result<SbioConnection, &'static str> {
-        let handle = match open(channel_name, flags) {
-            Ok(handle) => handle,
-            Err(err) => return Err(err),
-        };
-
-        let connection_data = SbioConnectionData {
-            channel_handle: handle,
-            channel_open: true,
-        };
+        target: &str,
+        name: &str,
+        format: &str,
+        values: &SbioValueMap,
+    ) -> Result<SbioSerializeData, SbioError> {
+        let payload = values.encode(&Format::parse(format)?)?;
+        let buffer = serialize_bytes(target, name, format, &payload)?;
 
-        Ok(SbioConnection {
-            flags: 0,
-            thread_handle: Some(spawn(|| {})),
-            thread_data: Arc::new(Mutex::new(connection_data)),
-        })
+        Ok(SbioSerializeData::new(buffer))
     }
 
-    pub fn connect_send(&mut self, channel_name: &str) -> Result<SbioConnection, &'static str> {
-        self.connect(channel_name, SBIO_FLAGS::WRONLY)
-    }
+    // Serialize an event whose format and payload are parsed from a data string such as
+    // `4u1:value 20 1s0:name Crank`
+    pub fn serialize_string(
+        &mut self,
+        target: &str,
+        name: &str,
+        data_string: &str,
+    ) -> Result<SbioSerializeData, SbioError> {
+        let (format, payload) = string_to_event_data(data_string)?;
+        let buffer = serialize_bytes(target, name, &format, &payload)?;
 
-    pub fn connect_receive(&mut self, channel_name: &str) -> Result<SbioConnection, &'static str> {
-        self.connect(channel_name, SBIO_FLAGS::RDONLY | SBIO_FLAGS::NONBLOCK)
+        Ok(SbioSerializeData::new(buffer))
     }
 
-    pub fn serialize<T>(
+    // Serialize an event whose format and payload are parsed from command line style arguments
+    // such as `["4u1:value", "20", "1s0:name", "Crank"]`
+    pub fn serialize_args(
         &mut self,
         target: &str,
         name: &str,
-        format: &str,
-        data: T,
-        size: u32,
-    ) -> Result<SbioSerializeData, &'static str> {
-        let buffer = match serialize(target, name, format, data, size) {
-            Ok(buffer) => buffer,
-            Err(err) => return Err(err),
-        };
+        args: &[&str],
+    ) -> Result<SbioSerializeData, SbioError> {
+        let (format, payload) = array_to_event_data(args)?;
+        let buffer = serialize_bytes(target, name, &format, &payload)?;
 
-        Ok(SbioSerializeData { buffer })
+        Ok(SbioSerializeData::new(buffer))
+    }
+
+    // Serialize a value using the event name, target and format from its SbioPayload impl
+    pub fn serialize_value<T: SbioPayload>(
+        &mut self,
+        value: &T,
+    ) -> Result<SbioSerializeData, SbioError> {
+        let buffer = serialize_bytes(T::TARGET, T::NAME, T::FORMAT, &value.encode())?;
+
+        Ok(SbioSerializeData::new(buffer))
     }
 }
 
@@ -159,7 +1286,7 @@ impl Sbio {
 mod tests {
     use super::*;
 
-    #[derive(PartialEq, Debug, Clone)]
+    #[derive(PartialEq, Debug, Clone, Copy)]
     struct TestData {
         var1: u32,
         var2: u16,
@@ -187,33 +1314,76 @@ mod tests {
         let mut sbio = Sbio();
         let target_in = "target";
         let name_in = "event1";
-        let format_in = "4s1 var1 2u1 var2 2u1 var2";
+        let format_in = "4s1 var1 2u1 var2 2u1 var3";
         let data_in = TestData {
             var1: 1,
             var2: 2,
             var3: 3,
         };
-        let size_in = 10;
+        let size_in = 8;
 
         let result = sbio.serialize(target_in, name_in, format_in, data_in, size_in);
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn serialize_checked_test() {
+        let mut sbio = Sbio();
+        let format_in = "4u1 var1 2u1 var2 2u1 var3";
+        let data_in = TestData {
+            var1: 1,
+            var2: 2,
+            var3: 3,
+        };
+
+        let result = sbio.serialize_checked("target", "event1", format_in, data_in);
+        let serialized_data = result.unwrap();
+        assert_eq!(serialized_data.payload().len(), 8);
+        assert_eq!(
+            *unsafe { serialized_data.data::<TestData>() }.unwrap(),
+            data_in
+        );
+
+        let result = sbio.serialize_checked("target", "event1", "4u1 var1 2u1 var2", data_in);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::SizeMismatch {
+                size: 6,
+                data_size: 8
+            })
+        );
+
+        let result = sbio.serialize_checked("target", "event1", "4u1 var1 2u1", data_in);
+        assert!(matches!(result.err(), Some(SbioError::Format(_))));
+
+        let result = sbio.serialize_checked("t", "n", "4u4611686018427387904 x", 1u32);
+        assert!(matches!(result.err(), Some(SbioError::Format(_))));
+
+        let result = sbio.serialize("target", "event1", format_in, data_in, 10);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::SizeMismatch {
+                size: 10,
+                data_size: 8
+            })
+        );
+    }
+
     #[test]
     fn unserialize_test() {
         let mut sbio = Sbio();
         let target_in = "target";
         let name_in = "event1";
-        let format_in = "4s1 var1 2u1 var2 2u1 var2";
+        let format_in = "4s1 var1 2u1 var2 2u1 var3";
         let data_in = TestData {
             var1: 1,
             var2: 2,
             var3: 3,
         };
-        let size_in = 10;
+        let size_in = 8;
 
-        let result = sbio.serialize(target_in, name_in, format_in, data_in.clone(), size_in);
-        let mut serialized_data = match result {
+        let result = sbio.serialize(target_in, name_in, format_in, data_in, size_in);
+        let serialized_data = match result {
             Ok(data) => data,
             Err(_) => panic!(),
         };
@@ -223,16 +1393,560 @@ mod tests {
 
         let target = serialized_data.target();
         assert_eq!(target, target_in);
-        
+
         let format = serialized_data.format();
         assert_eq!(format, format_in);
-    
-        let result: Result<&TestData, &'static str> = serialized_data.data();
+
+        let result: Result<Cow<TestData>, SbioError> = unsafe { serialized_data.data() };
         let data = match result {
             Ok(data) => data,
             Err(_) => panic!(),
         };
-        assert_eq!(data, &data_in);
+        assert_eq!(*data, data_in);
+    }
+
+    #[test]
+    fn serialize_string_test() {
+        let mut sbio = Sbio();
+
+        let result = sbio.serialize_string("target", "event1", "4u1:value 20 1s0:name Crank");
+        let serialized_data = result.unwrap();
+        assert_eq!(serialized_data.format(), "4u1 value 1s0 name");
+        assert_eq!(
+            serialized_data.data_string().unwrap(),
+            "4u1:value 20 1s0:name \"Crank\""
+        );
+
+        let args = ["4u1:value", "20", "1s0:name", "Crank"];
+        let result = sbio.serialize_args("target", "event1", &args);
+        let serialized_args = result.unwrap();
+        assert_eq!(serialized_args.format(), serialized_data.format());
+        assert_eq!(serialized_args.payload(), serialized_data.payload());
+    }
+
+    #[test]
+    fn send_buffer_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("send_buffer_test").unwrap();
+        let mut send = sbio.connect_send("send_buffer_test").unwrap();
+
+        let mut buffer = SbioBuffer::with_capacity(256).unwrap();
+        assert!(buffer.capacity() >= 256);
+        assert!(buffer.is_empty());
+
+        for value in 0..3u32 {
+            buffer
+                .serialize("target", "event1", "4u1 value", value, 4)
+                .unwrap();
+            assert!(!buffer.is_empty());
+            assert!(buffer.capacity() >= 256);
+            send.send_buffer(&buffer).unwrap();
+        }
+        buffer.clear();
+        assert!(buffer.is_empty());
+
+        for value in 0..3u32 {
+            let event = rcv.receive().unwrap();
+            assert_eq!(event.name(), "event1");
+            assert_eq!(*unsafe { event.data::<u32>() }.unwrap(), value);
+        }
+        assert!(rcv.try_receive().unwrap().is_none());
+
+        let mut buffer = SbioBuffer::new();
+        assert_eq!(buffer.capacity(), 0);
+        let result = buffer.serialize("target", "event1", "4u1 value", 1u32, 8);
+        assert!(result.is_err());
+        assert_eq!(buffer.capacity(), 0);
+    }
+
+    #[test]
+    fn receive_into_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("receive_into_test").unwrap();
+        let mut send = sbio.connect_send("receive_into_test").unwrap();
+
+        let mut values = SbioValueMap::new();
+        values.insert("label", SbioValue::String(String::from("Crank")));
+        send.send_values("target", "event1", "1s0 label", &values)
+            .unwrap();
+        send.send_event("target", "event2", "4u1 value", 20u32, 4)
+            .unwrap();
+
+        let mut buffer = SbioBuffer::new();
+        assert_eq!(buffer.name(), "");
+        assert!(buffer.payload().is_empty());
+        let size = rcv.receive_into(&mut buffer).unwrap();
+        assert_eq!(size, buffer.len());
+        assert_eq!(buffer.target(), "target");
+        assert_eq!(buffer.name(), "event1");
+        assert_eq!(buffer.values().unwrap(), values);
+        let capacity = buffer.capacity();
+        assert!(capacity >= size);
+
+        rcv.receive_into(&mut buffer).unwrap();
+        assert_eq!(buffer.name(), "event2");
+        assert_eq!(buffer.format(), "4u1 value");
+        assert_eq!(buffer.payload(), 20u32.to_ne_bytes());
+        assert_eq!(buffer.capacity(), capacity);
+
+        let result = rcv.receive_into(&mut buffer);
+        assert_eq!(result.unwrap_err().code(), Some(SbioErrorCode::QueueEmpty));
+    }
+
+    #[test]
+    fn max_message_size_test() {
+        let mut sbio = Sbio();
+        let _rcv = sbio.connect_receive("max_message_size_test").unwrap();
+        let mut send = sbio.connect_send("max_message_size_test").unwrap();
+
+        // The SysV queue greio used for testing doesn't report or change a limit
+        assert_eq!(send.max_message_size(), None);
+        let buffer = SbioBuffer::with_capacity(4096).unwrap();
+        assert!(matches!(
+            send.grow_to(&buffer),
+            Err(SbioError::Unsupported(_))
+        ));
+
+        // errno left over from an unrelated failure doesn't count as a greio error
+        assert!(std::fs::File::open("/nonexistent/max_message_size_test").is_err());
+        assert_ne!(std::io::Error::last_os_error().raw_os_error(), Some(0));
+        assert!(matches!(
+            send.grow_to(&buffer),
+            Err(SbioError::Unsupported(_))
+        ));
+
+        assert_eq!(check_message_size(100, None), Ok(()));
+        assert_eq!(check_message_size(100, Some(100)), Ok(()));
+        let err = check_message_size(101, Some(100)).unwrap_err();
+        assert_eq!(
+            err,
+            SbioError::MessageTooLarge {
+                size: 101,
+                max: 100
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Message is 101 bytes but the channel limit is 100"
+        );
+    }
+
+    #[test]
+    fn send_fragmented_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("send_fragmented_test").unwrap();
+        let mut send = sbio.connect_send("send_fragmented_test").unwrap();
+
+        let payload: Vec<u8> = (0..10000).map(|value| value as u8).collect();
+        let mut fragmenter = SbioFragmenter::new(1024);
+        let count = send
+            .send_fragmented(&mut fragmenter, "target", "blob", "1u10000 data", &payload)
+            .unwrap();
+        assert_eq!(count, 10);
+        assert!(send.connection.send_buffer.capacity() < payload.len());
+        send.send_event("target", "event1", "4u1 value", 20u32, 4)
+            .unwrap();
+
+        let mut reassembler = SbioReassembler::new(Duration::from_millis(100));
+        let event = rcv.receive_reassembled(&mut reassembler).unwrap();
+        assert_eq!(event.name, "blob");
+        assert_eq!(event.format, "1u10000 data");
+        assert_eq!(event.payload, payload);
+
+        let event = rcv.receive_reassembled(&mut reassembler).unwrap();
+        assert_eq!(event.name, "event1");
+
+        let result = rcv.receive_reassembled(&mut reassembler);
+        assert_eq!(result.unwrap_err().code(), Some(SbioErrorCode::Timeout));
+
+        // An event that fits is sent as is
+        let count = send
+            .send_fragmented(
+                &mut fragmenter,
+                "target",
+                "small",
+                "1u4 data",
+                &[1, 2, 3, 4],
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+        let event = rcv.receive_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(event.format(), "1u4 data");
+
+        // With a channel limit, fragments are sized to fit it whatever the fragmenter's size
+        send.connection.max_message = Some(512);
+        let mut fragmenter = SbioFragmenter::default();
+        let count = send
+            .send_fragmented(&mut fragmenter, "target", "blob", "1u10000 data", &payload)
+            .unwrap();
+        assert!(count > 10000 / 512);
+        let event = rcv.receive_reassembled(&mut reassembler).unwrap();
+        assert_eq!(event.payload, payload);
+
+        let count = send
+            .send_fragmented(&mut fragmenter, "target", "small", "1u400 data", &[7; 400])
+            .unwrap();
+        assert_eq!(count, 1);
+        let event = rcv.receive_reassembled(&mut reassembler).unwrap();
+        assert_eq!(event.format, "1u400 data");
+
+        // The overhead is what serializing adds to a payload
+        let mut buffer = SbioBuffer::new();
+        buffer
+            .serialize_bytes("target", "small", "1u400 data", &[7; 400])
+            .unwrap();
+        let overhead = message_overhead("target", "small", "1u400 data").unwrap();
+        assert_eq!(buffer.len(), overhead + 400);
+
+        // An event exactly at the limit still fits
+        send.connection.max_message = Some(overhead + 400);
+        let count = send
+            .send_fragmented(&mut fragmenter, "target", "small", "1u400 data", &[7; 400])
+            .unwrap();
+        assert_eq!(count, 1);
+        let event = rcv.receive_reassembled(&mut reassembler).unwrap();
+        assert_eq!(event.payload, vec![7; 400]);
+
+        let result = fragment_data_limit("target", "blob", 32);
+        assert!(matches!(
+            result,
+            Err(SbioError::MessageTooLarge { max: 32, .. })
+        ));
+    }
+
+    #[test]
+    fn exclusive_receive_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio
+            .connect_exclusive_receive("exclusive_receive_test")
+            .unwrap();
+        let mut send = sbio.connect_send("exclusive_receive_test").unwrap();
+
+        send.send_event("target", "event1", "4u1 value", 20u32, 4)
+            .unwrap();
+        let event = rcv.receive_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(*unsafe { event.data::<u32>() }.unwrap(), 20);
+
+        let busy = SbioError::Greio {
+            context: "Couldn't open SBIO channel",
+            code: SbioErrorCode::Unknown,
+            sys_code: libc::EBUSY,
+            message: String::from("Device or resource busy"),
+        };
+        assert_eq!(
+            exclusive_open_error("exclusive_receive_test", busy),
+            SbioError::ChannelInUse {
+                channel: String::from("exclusive_receive_test")
+            }
+        );
+        let other = SbioError::Greio {
+            context: "Couldn't open SBIO channel",
+            code: SbioErrorCode::Unknown,
+            sys_code: libc::ENOENT,
+            message: String::from("No such file or directory"),
+        };
+        assert_eq!(
+            exclusive_open_error("exclusive_receive_test", other.clone()),
+            other
+        );
+        let denied = SbioError::Greio {
+            context: "Couldn't open SBIO channel",
+            code: SbioErrorCode::Access,
+            sys_code: libc::EACCES,
+            message: String::from("Permission denied"),
+        };
+        assert_eq!(
+            exclusive_open_error("exclusive_receive_test", denied.clone()),
+            denied
+        );
+    }
+
+    #[test]
+    fn connection_builder_test() {
+        let name = ChannelName::new("connection_builder_test").unwrap();
+        let builder = SbioConnectionBuilder::new(name);
+        assert_eq!(builder.flags(), SBIO_FLAGS::RDONLY);
+        let builder = builder.exclusive_read().nonblocking(true);
+        assert_eq!(builder.flags(), SBIO_FLAGS::XRDONLY | SBIO_FLAGS::NONBLOCK);
+        let builder = builder.write();
+        assert_eq!(builder.flags(), SBIO_FLAGS::WRONLY | SBIO_FLAGS::NONBLOCK);
+        let builder = builder.nonblocking(false).read();
+        assert_eq!(builder.flags(), SBIO_FLAGS::RDONLY);
+
+        let mut sbio = Sbio();
+        let mut rcv = sbio
+            .connection("connection_builder_test")
+            .unwrap()
+            .read()
+            .receiver()
+            .unwrap();
+        let mut send = sbio
+            .connection("connection_builder_test")
+            .unwrap()
+            .write()
+            .nonblocking(true)
+            .send_buffer_capacity(1024)
+            .message_size_hint(16384)
+            .sender()
+            .unwrap();
+        assert!(send.connection.send_buffer.capacity() >= 1024);
+
+        // A blocking reader waits in receive rather than reporting QueueEmpty
+        send.send_event("target", "event1", "4u1 value", 20u32, 4)
+            .unwrap();
+        let event = rcv.receive().unwrap();
+        assert_eq!(*unsafe { event.data::<u32>() }.unwrap(), 20);
+        assert!(matches!(
+            rcv.receive_timeout(Duration::from_millis(1)),
+            Err(SbioError::Unsupported(_))
+        ));
+
+        let builder = sbio.connection("connection_builder_test").unwrap();
+        let result = builder.read().sender();
+        assert!(matches!(result, Err(SbioError::Unsupported(_))));
+        let result = sbio
+            .connection("connection_builder_test")
+            .unwrap()
+            .write()
+            .receiver();
+        assert!(matches!(result, Err(SbioError::Unsupported(_))));
+
+        // A channel opens in one mode, so exclusive read and write can't be combined
+        let mixed = SBIO_FLAGS::XRDONLY | SBIO_FLAGS::WRONLY;
+        let builder = sbio.connection("connection_builder_test").unwrap();
+        let result = builder.with_flags(mixed).sender();
+        assert_eq!(result.err(), Some(SbioError::InvalidFlags(mixed)));
+        let result = sbio.connect("connection_builder_test", mixed);
+        assert_eq!(result.err(), Some(SbioError::InvalidFlags(mixed)));
+    }
+
+    #[test]
+    fn channel_name_test() {
+        let mut sbio = Sbio();
+
+        let result = sbio.connect_receive("channel_name_test/invalid");
+        assert_eq!(
+            result.err(),
+            Some(SbioError::ChannelName(ChannelNameError::InvalidChar {
+                position: 17,
+                character: '/'
+            }))
+        );
+        let result = sbio.connect_send("channel_name_test_too_long");
+        assert!(matches!(
+            result.err(),
+            Some(SbioError::ChannelName(ChannelNameError::TooLong { .. }))
+        ));
+        let result = sbio.connect_duplex("channel_name_test", "nul\0");
+        assert!(matches!(
+            result.err(),
+            Some(SbioError::ChannelName(ChannelNameError::Nul {
+                position: 3
+            }))
+        ));
+
+        let name: ChannelName = "channel_name_test".parse().unwrap();
+        let _rcv = sbio.connect_receive(&name).unwrap();
+        let _send = sbio.connect_send(name).unwrap();
+    }
+
+    #[test]
+    fn invalid_string_test() {
+        let mut sbio = Sbio();
+        let _rcv = sbio.connect_receive("invalid_string_test").unwrap();
+        let mut send = sbio.connect_send("invalid_string_test").unwrap();
+
+        let result = send.send_event("tar\0get", "event1", "4u1 value", 20u32, 4);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::InvalidString { argument: "target" })
+        );
+        let result = send.send_event("target", "event\0", "4u1 value", 20u32, 4);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::InvalidString { argument: "name" })
+        );
+        let result = sbio.serialize("target", "event1", "4u1 value\0", 20u32, 4);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::InvalidString { argument: "format" })
+        );
+
+        let mut mdata = SbioMultiData::new();
+        let result = mdata.add_value("app.\0speed", &SbioValue::U32(20));
+        assert_eq!(
+            result.err(),
+            Some(SbioError::InvalidString { argument: "key" })
+        );
+
+        let mut buffer = SbioBuffer::new();
+        let result = buffer.serialize_bytes("target", "event1", "1u1 value", &[1]);
+        assert!(result.is_ok());
+        let result = buffer.serialize_bytes("target\0", "event1", "1u1 value", &[1]);
+        assert!(result.is_err());
+        // A failed serialize leaves the previous event in place
+        assert_eq!(buffer.target(), "target");
+
+        // The connection is still usable after rejected sends
+        send.send_event("target", "event1", "4u1 value", 20u32, 4)
+            .unwrap();
+    }
+
+    #[test]
+    fn non_utf8_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("non_utf8_test").unwrap();
+        let mut send = sbio.connect_send("non_utf8_test").unwrap();
+
+        // A Latin-1 target and name, as sent by a peer that doesn't use UTF-8
+        let target = CStr::from_bytes_with_nul(b"caf\xe9\0").unwrap();
+        let name = CStr::from_bytes_with_nul(b"d\xe9part\0").unwrap();
+        let format = CStr::from_bytes_with_nul(b"4u1 value\0").unwrap();
+        let mut buffer = SbioBuffer::new();
+        buffer
+            .serialize_cstr(target, name, format, &20u32.to_ne_bytes())
+            .unwrap();
+        assert_eq!(buffer.target_cstr(), target);
+        send.send_buffer(&buffer).unwrap();
+
+        let event = rcv.receive().unwrap();
+        assert_eq!(event.target_cstr(), target);
+        assert_eq!(event.name_cstr(), name);
+        assert_eq!(event.target(), "caf\u{FFFD}");
+        assert_eq!(event.name(), "d\u{FFFD}part");
+        assert_eq!(
+            event.try_target().err(),
+            Some(SbioError::InvalidUtf8 { field: "target" })
+        );
+        assert_eq!(
+            event.try_name().err(),
+            Some(SbioError::InvalidUtf8 { field: "name" })
+        );
+        assert_eq!(event.try_format(), Ok("4u1 value"));
+        assert_eq!(*unsafe { event.data::<u32>() }.unwrap(), 20);
+
+        let event = event.into_owned();
+        assert_eq!(event.target, "caf\u{FFFD}");
+    }
+
+    #[test]
+    fn send_mdata_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("send_mdata_test").unwrap();
+        let mut send = sbio.connect_send("send_mdata_test").unwrap();
+
+        let mut mdata = SbioMultiData::new();
+        assert_eq!(send.send_mdata(&mdata).unwrap(), 0);
+
+        mdata
+            .add_value("app.speed", &SbioValue::U32(20))
+            .unwrap()
+            .add_bytes("app.label", "1s0", b"Crank\0")
+            .unwrap();
+        assert_eq!(mdata.len(), 2);
+
+        let result = mdata.add_value("app.empty", &SbioValue::Array(Vec::new()));
+        assert!(result.is_err());
+        assert_eq!(mdata.len(), 2);
+
+        send.send_mdata(&mdata).unwrap();
+        let event = rcv.receive().unwrap();
+        assert!(event.is_mdata());
+        let entries: Vec<(String, SbioValue)> = event
+            .mdata()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.key().into_owned(), entry.value().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (String::from("app.speed"), SbioValue::U32(20)),
+                (
+                    String::from("app.label"),
+                    SbioValue::String(String::from("Crank"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn send_receive_values_test() {
+        let mut sbio = Sbio();
+        let mut rcv = match sbio.connect_receive("send_receive_values_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem receiving event: {:?}", err),
+        };
+        let mut send = match sbio.connect_send("send_receive_values_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem sending event: {:?}", err),
+        };
+
+        let mut values = SbioValueMap::new();
+        values.insert("value", SbioValue::U32(20));
+        values.insert("name", SbioValue::String(String::from("Crank")));
+        let result = send.send_values("target", "event1", "4u1 value 1s0 name", &values);
+        assert!(result.is_ok());
+
+        let event = rcv.receive_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.values(), Ok(values));
+    }
+
+    #[test]
+    fn data_size_test() {
+        let mut sbio = Sbio();
+        let result = sbio.serialize("target", "event1", "4u1 var1", 1u32, 4);
+        let serialized_data = match result {
+            Ok(data) => data,
+            Err(_) => panic!(),
+        };
+
+        let result = unsafe { serialized_data.data::<TestData>() };
+        assert_eq!(
+            result.err(),
+            Some(SbioError::PayloadSize {
+                expected: 8,
+                actual: 4
+            })
+        );
+
+        let result = unsafe { serialized_data.data::<u32>() };
+        assert_eq!(*result.unwrap(), 1);
+
+        let result = unsafe { serialized_data.data::<u16>() };
+        assert_eq!(
+            result.err(),
+            Some(SbioError::PayloadSize {
+                expected: 2,
+                actual: 4
+            })
+        );
+    }
+
+    #[test]
+    fn into_owned_test() {
+        let mut sbio = Sbio();
+        let data_in = TestData {
+            var1: 1,
+            var2: 2,
+            var3: 3,
+        };
+
+        let result = sbio.serialize("target", "event1", "4u1 var1 2u1 var2 2u1 var3", data_in, 8);
+        let serialized_data = match result {
+            Ok(data) => data,
+            Err(_) => panic!(),
+        };
+
+        let event = serialized_data.into_owned();
+        let handle = spawn(move || event);
+        let event = handle.join().unwrap();
+        assert_eq!(event.target, "target");
+        assert_eq!(event.name, "event1");
+        assert_eq!(event.format, "4u1 var1 2u1 var2 2u1 var3");
+        assert_eq!(event.payload, vec![1, 0, 0, 0, 2, 0, 3, 0]);
     }
 
     #[test]
@@ -255,7 +1969,7 @@ mod tests {
                 var2: 2,
                 var3: 3,
             },
-            10,
+            8,
         );
         assert!(result.is_ok());
         let result = rcv.receive();
@@ -263,4 +1977,128 @@ mod tests {
         rcv.close();
         send.close();
     }
+
+    #[test]
+    fn try_receive_test() {
+        let mut sbio = Sbio();
+        let mut rcv = match sbio.connect_receive("try_receive_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem receiving event: {:?}", err),
+        };
+        let mut send = match sbio.connect_send("try_receive_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem sending event: {:?}", err),
+        };
+
+        let result = rcv.try_receive();
+        assert!(result.unwrap().is_none());
+
+        let result = send.send_event("target", "event1", "", 0u8, 0);
+        assert!(result.is_ok());
+
+        let result = rcv.try_receive();
+        let event = result.unwrap().unwrap();
+        assert_eq!(event.name(), "event1");
+
+        let result = rcv.try_receive();
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn duplex_test() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut sbio = Sbio();
+        let mut b_rcv = match sbio.connect_receive("duplex_test_up") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem receiving event: {:?}", err),
+        };
+        let (mut a_send, mut a_rcv) =
+            match sbio.connect_duplex("duplex_test_up", "duplex_test_down") {
+                Ok(pair) => pair,
+                Err(err) => panic!("Problem opening channels: {:?}", err),
+            };
+        let mut b_send = match sbio.connect_send("duplex_test_down") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem sending event: {:?}", err),
+        };
+        assert_send(&a_send);
+        assert_send(&a_rcv);
+
+        let handle = spawn(move || {
+            let event = b_rcv.receive_timeout(Duration::from_secs(5)).unwrap();
+            let name = String::from(event.name());
+            b_send.send_event("target", &name, "", 0u8, 0).unwrap();
+        });
+
+        let result = a_send.send_event("target", "ping", "", 0u8, 0);
+        assert!(result.is_ok());
+        let event = a_rcv.receive_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.name(), "ping");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn receive_timeout_test() {
+        let mut sbio = Sbio();
+        let mut rcv = match sbio.connect_receive("receive_timeout_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem receiving event: {:?}", err),
+        };
+        let mut send = match sbio.connect_send("receive_timeout_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem sending event: {:?}", err),
+        };
+
+        let start = Instant::now();
+        let result = rcv.receive_timeout(Duration::from_millis(50));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let err = result.err().unwrap();
+        assert_eq!(err.code(), Some(SbioErrorCode::Timeout));
+
+        let result = send.send_event("target", "event1", "", 0u8, 0);
+        assert!(result.is_ok());
+
+        let result = rcv.receive_timeout(Duration::from_secs(5));
+        let event = result.unwrap();
+        assert_eq!(event.name(), "event1");
+    }
+
+    #[test]
+    fn on_event_test() {
+        let mut sbio = Sbio();
+        let mut rcv = match sbio.connect_receive("on_event_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem receiving event: {:?}", err),
+        };
+        let mut send = match sbio.connect_send("on_event_test") {
+            Ok(connection) => connection,
+            Err(err) => panic!("Problem sending event: {:?}", err),
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let result = rcv.on_event(move |event| {
+            tx.send(String::from(event.name())).unwrap();
+        });
+        assert!(result.is_ok());
+        assert!(rcv.is_listening());
+
+        let result = rcv.on_event(|_| {});
+        assert!(result.is_err());
+
+        for name in ["event1", "event2", "event3"].iter() {
+            let result = send.send_event("target", name, "", 0u8, 0);
+            assert!(result.is_ok());
+        }
+        for name in ["event1", "event2", "event3"].iter() {
+            let received = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(&received, name);
+        }
+
+        let result = rcv.stop_listener();
+        assert!(result.is_ok());
+        assert!(!rcv.is_listening());
+
+        rcv.close();
+    }
 }
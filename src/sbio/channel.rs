@@ -0,0 +1,193 @@
+//! Validated greio channel names.
+
+use sbio::sbio_sys::GRE_IO_MAX_NAME_LEN;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest channel name greio accepts, leaving room for the nul terminator
+pub const MAX_CHANNEL_NAME_LEN: usize = GRE_IO_MAX_NAME_LEN as usize - 1;
+
+/// Why a channel name was rejected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelNameError {
+    Empty,
+    /// The name is `len` bytes but greio only accepts `max`
+    TooLong {
+        len: usize,
+        max: usize,
+    },
+    /// The name contains a nul byte at byte offset `position`
+    Nul {
+        position: usize,
+    },
+    /// The character at byte offset `position` isn't an ASCII letter, digit, `_`, `-` or `.`
+    InvalidChar {
+        position: usize,
+        character: char,
+    },
+}
+
+impl fmt::Display for ChannelNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelNameError::Empty => write!(f, "Channel name is empty"),
+            ChannelNameError::TooLong { len, max } => write!(
+                f,
+                "Channel name is {} bytes but at most {} are allowed",
+                len, max
+            ),
+            ChannelNameError::Nul { position } => {
+                write!(f, "Channel name contains a nul byte at {}", position)
+            }
+            ChannelNameError::InvalidChar {
+                position,
+                character,
+            } => write!(
+                f,
+                "Channel name contains invalid character {:?} at {}",
+                character, position
+            ),
+        }
+    }
+}
+
+impl Error for ChannelNameError {}
+
+/// A channel name that greio can open: 1 to `MAX_CHANNEL_NAME_LEN` ASCII letters, digits, `_`,
+/// `-` or `.`. Parse names when configuration is loaded to catch mistakes before connecting.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChannelName(String);
+
+impl ChannelName {
+    pub fn new(name: &str) -> Result<ChannelName, ChannelNameError> {
+        if name.is_empty() {
+            return Err(ChannelNameError::Empty);
+        }
+        for (position, character) in name.char_indices() {
+            if character == '\0' {
+                return Err(ChannelNameError::Nul { position });
+            }
+            let valid = character.is_ascii_alphanumeric()
+                || character == '_'
+                || character == '-'
+                || character == '.';
+            if !valid {
+                return Err(ChannelNameError::InvalidChar {
+                    position,
+                    character,
+                });
+            }
+        }
+        if name.len() > MAX_CHANNEL_NAME_LEN {
+            return Err(ChannelNameError::TooLong {
+                len: name.len(),
+                max: MAX_CHANNEL_NAME_LEN,
+            });
+        }
+
+        Ok(ChannelName(String::from(name)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ChannelName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ChannelName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ChannelName {
+    type Err = ChannelNameError;
+
+    fn from_str(name: &str) -> Result<ChannelName, ChannelNameError> {
+        ChannelName::new(name)
+    }
+}
+
+/// Values accepted as a channel name by the `Sbio::connect*` methods: an already validated
+/// `ChannelName`, or a string that is validated when connecting
+pub trait IntoChannelName {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError>;
+}
+
+impl IntoChannelName for ChannelName {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError> {
+        Ok(self)
+    }
+}
+
+impl IntoChannelName for &ChannelName {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoChannelName for &str {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError> {
+        ChannelName::new(self)
+    }
+}
+
+impl IntoChannelName for String {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError> {
+        ChannelName::new(&self)
+    }
+}
+
+impl IntoChannelName for &String {
+    fn into_channel_name(self) -> Result<ChannelName, ChannelNameError> {
+        ChannelName::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_name_test() {
+        let name = ChannelName::new("sensor.stream-1_a").unwrap();
+        assert_eq!(name.as_str(), "sensor.stream-1_a");
+        assert_eq!(name.to_string(), "sensor.stream-1_a");
+        assert_eq!("sensor.stream-1_a".parse::<ChannelName>(), Ok(name));
+
+        let longest = "a".repeat(MAX_CHANNEL_NAME_LEN);
+        assert!(ChannelName::new(&longest).is_ok());
+
+        let cases = [
+            ("", ChannelNameError::Empty),
+            (
+                "abcdefghijklmnopqrstuvwxy",
+                ChannelNameError::TooLong { len: 25, max: 24 },
+            ),
+            ("abc\0def", ChannelNameError::Nul { position: 3 }),
+            (
+                "abc/def",
+                ChannelNameError::InvalidChar {
+                    position: 3,
+                    character: '/',
+                },
+            ),
+            (
+                "näme",
+                ChannelNameError::InvalidChar {
+                    position: 1,
+                    character: 'ä',
+                },
+            ),
+        ];
+        for &(name, ref err) in cases.iter() {
+            assert_eq!(ChannelName::new(name).as_ref(), Err(err), "{:?}", name);
+        }
+    }
+}
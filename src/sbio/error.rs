@@ -0,0 +1,353 @@
+#![allow(non_upper_case_globals)]
+
+use super::channel::ChannelNameError;
+use super::format::FormatError;
+use super::sbio_sys::*;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+
+/// The greio error code reported by `gre_io_get_error_codes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SbioErrorCode {
+    NoError,
+    Unknown,
+    OutOfMemory,
+    Access,
+    QueueEmpty,
+    QueueFull,
+    Timeout,
+}
+
+impl SbioErrorCode {
+    /// Map a raw `gre_io_error_codes` value, treating anything unrecognised as `Unknown`
+    pub fn from_raw(code: i32) -> SbioErrorCode {
+        match code as gre_io_error_codes {
+            gre_io_error_codes_GREIO_ERROR_NO_ERROR => SbioErrorCode::NoError,
+            gre_io_error_codes_GREIO_ERROR_OUT_OF_MEMORY => SbioErrorCode::OutOfMemory,
+            gre_io_error_codes_GREIO_ERROR_ACCESS => SbioErrorCode::Access,
+            gre_io_error_codes_GREIO_ERROR_QUEUE_EMPTY => SbioErrorCode::QueueEmpty,
+            gre_io_error_codes_GREIO_ERROR_QUEUE_FULL => SbioErrorCode::QueueFull,
+            gre_io_error_codes_GREIO_ERROR_TIMEOUT => SbioErrorCode::Timeout,
+            _ => SbioErrorCode::Unknown,
+        }
+    }
+
+    pub fn as_raw(&self) -> i32 {
+        let code = match self {
+            SbioErrorCode::NoError => gre_io_error_codes_GREIO_ERROR_NO_ERROR,
+            SbioErrorCode::Unknown => gre_io_error_codes_GREIO_ERROR_UNKNOWN,
+            SbioErrorCode::OutOfMemory => gre_io_error_codes_GREIO_ERROR_OUT_OF_MEMORY,
+            SbioErrorCode::Access => gre_io_error_codes_GREIO_ERROR_ACCESS,
+            SbioErrorCode::QueueEmpty => gre_io_error_codes_GREIO_ERROR_QUEUE_EMPTY,
+            SbioErrorCode::QueueFull => gre_io_error_codes_GREIO_ERROR_QUEUE_FULL,
+            SbioErrorCode::Timeout => gre_io_error_codes_GREIO_ERROR_TIMEOUT,
+        };
+        code as i32
+    }
+}
+
+/// Errors returned by the SBIO wrappers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SbioError {
+    /// A greio call failed. `code` and `sys_code` are the values reported by
+    /// `gre_io_get_error_codes` and `message` is the matching `gre_io_get_error_message` text.
+    Greio {
+        context: &'static str,
+        code: SbioErrorCode,
+        sys_code: i32,
+        message: String,
+    },
+    /// A channel name was rejected before opening the channel
+    ChannelName(ChannelNameError),
+    /// An exclusive reader couldn't open the channel because another reader owns it
+    ChannelInUse { channel: String },
+    /// The operation isn't possible with the way the channel was opened
+    Unsupported(&'static str),
+    /// The channel or sink named has already been closed
+    Closed(&'static str),
+    /// The flags combine more than one of the read, exclusive read and write modes
+    InvalidFlags(SBIO_FLAGS),
+    /// The callback passed to `SbioConnection::on_event` panicked
+    ListenerPanicked,
+    /// An event payload isn't the size of the type it was read as
+    PayloadSize { expected: usize, actual: usize },
+    /// A payload size doesn't match the size of the data value being sent
+    SizeMismatch { size: usize, data_size: usize },
+    /// A serialized message is bigger than the channel's message size limit
+    MessageTooLarge { size: usize, max: usize },
+    /// A format string couldn't be parsed
+    Format(FormatError),
+    /// A received event string isn't valid UTF-8
+    InvalidUtf8 { field: &'static str },
+    /// A string argument contains an interior nul byte
+    InvalidString { argument: &'static str },
+    /// A multi data record at `offset` in the payload doesn't fit within the payload
+    InvalidMultiData { offset: usize },
+    /// A fragment header is inconsistent with the rest of its transfer
+    InvalidFragment { transfer: u32 },
+    /// A fragment arrived out of sequence, so at least one fragment of the transfer was lost
+    MissingFragment {
+        transfer: u32,
+        expected: u32,
+        received: u32,
+    },
+    /// The next fragment of a transfer didn't arrive in time
+    FragmentTimeout {
+        transfer: u32,
+        received: u32,
+        count: u32,
+    },
+    /// A format field has no value to encode
+    MissingField(String),
+    /// A value doesn't match the type of its format field
+    InvalidValue { field: String, spec: String },
+}
+
+// The codes greio recorded for the last failure on a channel handle
+fn error_codes(handle: *mut gre_io_t) -> gre_io_error_t {
+    let mut codes = gre_io_error_t {
+        error_code: 0,
+        sys_code: 0,
+    };
+
+    unsafe {
+        gre_io_get_error_codes(handle, &mut codes as *mut gre_io_error_t);
+    }
+    codes
+}
+
+impl SbioError {
+    /// Build an error from the last failure recorded on a channel handle. A null handle is
+    /// allowed, which is what `open` uses since it has no handle to query. If greio didn't
+    /// record a code the error falls back to errno.
+    pub(crate) fn from_handle(context: &'static str, handle: *mut gre_io_t) -> SbioError {
+        let os_error = std::io::Error::last_os_error();
+        let mut codes = error_codes(handle);
+
+        if codes.error_code == SbioErrorCode::NoError.as_raw() {
+            return SbioError::Greio {
+                context,
+                code: SbioErrorCode::Unknown,
+                sys_code: os_error.raw_os_error().unwrap_or(0),
+                message: os_error.to_string(),
+            };
+        }
+
+        // Some implementations report an error code without the errno behind it
+        if codes.sys_code <= 0 {
+            codes.sys_code = os_error.raw_os_error().unwrap_or(codes.sys_code);
+        }
+        SbioError::from_codes(context, codes)
+    }
+
+    /// Whether greio recorded a cause for the last failure on a channel handle, either a code more
+    /// specific than `Unknown` or an errno. Unlike the errno fallback of `from_handle`, this never
+    /// picks up a value left over from an earlier call.
+    pub(crate) fn has_cause(handle: *mut gre_io_t) -> bool {
+        let codes = error_codes(handle);
+        match SbioErrorCode::from_raw(codes.error_code) {
+            SbioErrorCode::NoError | SbioErrorCode::Unknown => codes.sys_code > 0,
+            _ => true,
+        }
+    }
+
+    fn from_codes(context: &'static str, mut codes: gre_io_error_t) -> SbioError {
+        let message;
+
+        unsafe {
+            let message_ptr = gre_io_get_error_message(&mut codes as *mut gre_io_error_t);
+            message = if message_ptr.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(message_ptr).to_string_lossy().into_owned()
+            };
+        }
+
+        SbioError::Greio {
+            context,
+            code: SbioErrorCode::from_raw(codes.error_code),
+            sys_code: codes.sys_code,
+            message,
+        }
+    }
+
+    /// Build an error for calls that only report failure through errno, such as `gre_io_serialize`
+    pub(crate) fn from_errno(context: &'static str) -> SbioError {
+        SbioError::from_io(context, std::io::Error::last_os_error())
+    }
+
+    /// Build an error from a failed standard library operation the wrappers depend on
+    pub(crate) fn from_io(context: &'static str, err: std::io::Error) -> SbioError {
+        SbioError::Greio {
+            context,
+            code: SbioErrorCode::Unknown,
+            sys_code: err.raw_os_error().unwrap_or(0),
+            message: err.to_string(),
+        }
+    }
+
+    /// Build a timeout error for waits that are implemented on the Rust side
+    pub(crate) fn timeout(context: &'static str) -> SbioError {
+        let codes = gre_io_error_t {
+            error_code: SbioErrorCode::Timeout.as_raw(),
+            sys_code: libc::ETIMEDOUT,
+        };
+
+        SbioError::from_codes(context, codes)
+    }
+
+    /// The greio error code, or `None` if the error didn't come from greio
+    pub fn code(&self) -> Option<SbioErrorCode> {
+        match self {
+            SbioError::Greio { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The system errno captured alongside the greio error code
+    pub fn sys_code(&self) -> Option<i32> {
+        match self {
+            SbioError::Greio { sys_code, .. } => Some(*sys_code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SbioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SbioError::Greio {
+                context,
+                code,
+                sys_code,
+                message,
+            } => write!(
+                f,
+                "{}: {} ({:?}, errno {})",
+                context, message, code, sys_code
+            ),
+            SbioError::ChannelName(err) => err.fmt(f),
+            SbioError::ChannelInUse { channel } => {
+                write!(f, "Channel '{}' is already open by another reader", channel)
+            }
+            SbioError::Unsupported(reason) => write!(f, "Unsupported operation: {}", reason),
+            SbioError::Closed(what) => write!(f, "The {} has been closed", what),
+            SbioError::InvalidFlags(flags) => write!(
+                f,
+                "Invalid channel flags {:?}: a channel opens in a single read or write mode",
+                flags
+            ),
+            SbioError::ListenerPanicked => write!(f, "Listener callback panicked"),
+            SbioError::PayloadSize { expected, actual } => write!(
+                f,
+                "Event payload is {} bytes but {} were expected",
+                actual, expected
+            ),
+            SbioError::SizeMismatch { size, data_size } => write!(
+                f,
+                "Payload size {} doesn't match the {} byte data value",
+                size, data_size
+            ),
+            SbioError::MessageTooLarge { size, max } => write!(
+                f,
+                "Message is {} bytes but the channel limit is {}",
+                size, max
+            ),
+            SbioError::Format(err) => err.fmt(f),
+            SbioError::InvalidUtf8 { field } => write!(f, "Event {} isn't valid UTF-8", field),
+            SbioError::InvalidString { argument } => {
+                write!(f, "Invalid {}: contains a nul byte", argument)
+            }
+            SbioError::InvalidMultiData { offset } => {
+                write!(f, "Malformed multi data record at offset {}", offset)
+            }
+            SbioError::InvalidFragment { transfer } => {
+                write!(f, "Inconsistent fragment header in transfer {}", transfer)
+            }
+            SbioError::MissingFragment {
+                transfer,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Transfer {} expected fragment {} but received fragment {}",
+                transfer, expected, received
+            ),
+            SbioError::FragmentTimeout {
+                transfer,
+                received,
+                count,
+            } => write!(
+                f,
+                "Transfer {} timed out after {} of {} fragments",
+                transfer, received, count
+            ),
+            SbioError::MissingField(field) => write!(f, "No value for field '{}'", field),
+            SbioError::InvalidValue { field, spec } => {
+                write!(f, "Value for field '{}' doesn't match {}", field, spec)
+            }
+        }
+    }
+}
+
+impl Error for SbioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SbioError::ChannelName(err) => Some(err),
+            SbioError::Format(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ChannelNameError> for SbioError {
+    fn from(err: ChannelNameError) -> SbioError {
+        SbioError::ChannelName(err)
+    }
+}
+
+impl From<FormatError> for SbioError {
+    fn from(err: FormatError) -> SbioError {
+        SbioError::Format(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_code_round_trip_test() {
+        let codes = [
+            SbioErrorCode::NoError,
+            SbioErrorCode::Unknown,
+            SbioErrorCode::OutOfMemory,
+            SbioErrorCode::Access,
+            SbioErrorCode::QueueEmpty,
+            SbioErrorCode::QueueFull,
+            SbioErrorCode::Timeout,
+        ];
+        for code in codes.iter() {
+            assert_eq!(SbioErrorCode::from_raw(code.as_raw()), *code);
+        }
+        assert_eq!(SbioErrorCode::from_raw(-1), SbioErrorCode::Unknown);
+        assert_eq!(SbioErrorCode::from_raw(100), SbioErrorCode::Unknown);
+    }
+
+    #[test]
+    fn display_test() {
+        let err = SbioError::Greio {
+            context: "Couldn't send event",
+            code: SbioErrorCode::QueueFull,
+            sys_code: 11,
+            message: String::from("Message queue full"),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Couldn't send event: Message queue full (QueueFull, errno 11)"
+        );
+        assert_eq!(err.code(), Some(SbioErrorCode::QueueFull));
+        assert_eq!(err.sys_code(), Some(11));
+    }
+}
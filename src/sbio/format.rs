@@ -0,0 +1,454 @@
+//! Parser for Storyboard event format strings.
+//!
+//! A format string is a whitespace separated list of `<size><kind><count> <name>` pairs, for
+//! example `"4s1 var1 2u1 var2 1s0 label"`. The size is the number of bytes per element, the kind
+//! is `s` (signed), `u` (unsigned) or `f` (float) and the count is the number of elements. The
+//! special spec `1s0` describes a nul terminated string.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The element type of a format field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FieldKind {
+    Signed,
+    Unsigned,
+    Float,
+    String,
+}
+
+/// A single field of a format string
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Field {
+    /// Bytes per element
+    pub size: usize,
+    pub kind: FieldKind,
+    /// Number of elements, always 0 for strings
+    pub count: usize,
+    pub name: String,
+}
+
+impl Field {
+    /// The number of payload bytes the field occupies, or `None` for variable length strings.
+    /// Saturates at `usize::MAX` rather than overflowing.
+    pub fn byte_size(&self) -> Option<usize> {
+        match self.kind {
+            FieldKind::String => None,
+            _ => Some(self.size.saturating_mul(self.count)),
+        }
+    }
+
+    /// The `<size><kind><count>` part of the field
+    pub fn spec(&self) -> String {
+        let kind = match self.kind {
+            FieldKind::Signed | FieldKind::String => 's',
+            FieldKind::Unsigned => 'u',
+            FieldKind::Float => 'f',
+        };
+        format!("{}{}{}", self.size, kind, self.count)
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.spec(), self.name)
+    }
+}
+
+/// A parsed format string
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Format {
+    pub fields: Vec<Field>,
+}
+
+impl Format {
+    /// Parse a format string. An empty string is a valid format with no fields.
+    pub fn parse(format: &str) -> Result<Format, FormatError> {
+        let mut fields: Vec<Field> = Vec::new();
+        let mut tokens = Tokens::new(format);
+
+        while let Some((spec_column, spec)) = tokens.next() {
+            let (size, kind, count) = parse_spec(spec_column, spec)?;
+
+            let (name_column, name) = match tokens.next() {
+                Some(token) => token,
+                None => {
+                    return Err(FormatError::new(
+                        spec_column + spec.len(),
+                        FormatErrorKind::MissingName,
+                    ))
+                }
+            };
+            if !is_valid_name(name) {
+                return Err(FormatError::new(
+                    name_column,
+                    FormatErrorKind::InvalidName(String::from(name)),
+                ));
+            }
+            if fields.iter().any(|field| field.name == name) {
+                return Err(FormatError::new(
+                    name_column,
+                    FormatErrorKind::DuplicateName(String::from(name)),
+                ));
+            }
+
+            fields.push(Field {
+                size,
+                kind,
+                count,
+                name: String::from(name),
+            });
+        }
+
+        Ok(Format { fields })
+    }
+
+    /// Look up a field by name
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// The number of payload bytes described by the format, or `None` if it contains a string.
+    /// Saturates at `usize::MAX` rather than overflowing.
+    pub fn byte_size(&self) -> Option<usize> {
+        self.fields.iter().try_fold(0usize, |total, field| {
+            field.byte_size().map(|size| total.saturating_add(size))
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, field) in self.fields.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Format {
+    type Err = FormatError;
+
+    fn from_str(format: &str) -> Result<Format, FormatError> {
+        Format::parse(format)
+    }
+}
+
+/// What went wrong while parsing a format string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatErrorKind {
+    /// The field spec didn't start with an element size
+    MissingSize,
+    /// The element size isn't supported for the field kind
+    InvalidSize(usize),
+    /// The element size wasn't followed by a kind character
+    MissingKind,
+    /// The kind character isn't `s`, `u` or `f`
+    InvalidKind(char),
+    /// The field spec didn't end with an element count
+    MissingCount,
+    /// The element count is 0 for something other than a `1s0` string
+    InvalidCount(usize),
+    /// An element size or count, as written, is too large to be supported
+    OutOfRange(String),
+    /// A field spec wasn't followed by a name
+    MissingName,
+    InvalidName(String),
+    DuplicateName(String),
+}
+
+/// A format string syntax error. `column` is the 1-based character position of the problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatError {
+    pub column: usize,
+    pub kind: FormatErrorKind,
+}
+
+impl FormatError {
+    fn new(column: usize, kind: FormatErrorKind) -> FormatError {
+        FormatError { column, kind }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid format string at column {}: ", self.column)?;
+        match self.kind {
+            FormatErrorKind::MissingSize => write!(f, "expected an element size"),
+            FormatErrorKind::InvalidSize(size) => write!(f, "unsupported element size {}", size),
+            FormatErrorKind::MissingKind => write!(f, "expected 's', 'u' or 'f' after the size"),
+            FormatErrorKind::InvalidKind(kind) => {
+                write!(f, "unknown kind '{}', expected 's', 'u' or 'f'", kind)
+            }
+            FormatErrorKind::MissingCount => write!(f, "expected an element count"),
+            FormatErrorKind::InvalidCount(count) => write!(f, "invalid element count {}", count),
+            FormatErrorKind::OutOfRange(ref number) => write!(f, "{} is out of range", number),
+            FormatErrorKind::MissingName => write!(f, "expected a field name"),
+            FormatErrorKind::InvalidName(ref name) => write!(f, "invalid field name '{}'", name),
+            FormatErrorKind::DuplicateName(ref name) => {
+                write!(f, "duplicate field name '{}'", name)
+            }
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+// Whitespace separated tokens along with their 1-based starting column
+struct Tokens<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    source: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(source: &'a str) -> Tokens<'a> {
+        Tokens {
+            chars: source.char_indices().peekable(),
+            source,
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+
+        let (start, _) = *self.chars.peek()?;
+        let mut end = self.source.len();
+        while let Some(&(index, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                end = index;
+                break;
+            }
+            self.chars.next();
+        }
+
+        let column = self.source[..start].chars().count() + 1;
+        Some((column, &self.source[start..end]))
+    }
+}
+
+// greio sizes payloads with an int, so no field can hold more elements than this
+const MAX_COUNT: usize = i32::MAX as usize;
+
+fn parse_spec(column: usize, spec: &str) -> Result<(usize, FieldKind, usize), FormatError> {
+    let size_len = spec.chars().take_while(|c| c.is_ascii_digit()).count();
+    if size_len == 0 {
+        return Err(FormatError::new(column, FormatErrorKind::MissingSize));
+    }
+    let size_str = &spec[..size_len];
+    let size = size_str.parse().map_err(|_| {
+        FormatError::new(column, FormatErrorKind::OutOfRange(String::from(size_str)))
+    })?;
+
+    let kind_char = match spec[size_len..].chars().next() {
+        Some(kind_char) => kind_char,
+        None => {
+            return Err(FormatError::new(
+                column + size_len,
+                FormatErrorKind::MissingKind,
+            ))
+        }
+    };
+    let kind_column = column + size_len;
+    let mut kind = match kind_char {
+        's' => FieldKind::Signed,
+        'u' => FieldKind::Unsigned,
+        'f' => FieldKind::Float,
+        _ => {
+            return Err(FormatError::new(
+                kind_column,
+                FormatErrorKind::InvalidKind(kind_char),
+            ))
+        }
+    };
+
+    let count_start = size_len + kind_char.len_utf8();
+    let count_column = kind_column + 1;
+    let count_str = &spec[count_start..];
+    if count_str.is_empty() {
+        return Err(FormatError::new(
+            count_column,
+            FormatErrorKind::MissingCount,
+        ));
+    }
+    if let Some((offset, _)) = count_str
+        .chars()
+        .enumerate()
+        .find(|&(_, c)| !c.is_ascii_digit())
+    {
+        return Err(FormatError::new(
+            count_column + offset,
+            FormatErrorKind::MissingCount,
+        ));
+    }
+    let count = match count_str.parse() {
+        Ok(count) if count <= MAX_COUNT => count,
+        _ => {
+            return Err(FormatError::new(
+                count_column,
+                FormatErrorKind::OutOfRange(String::from(count_str)),
+            ))
+        }
+    };
+
+    if count == 0 {
+        if kind == FieldKind::Signed && size == 1 {
+            kind = FieldKind::String;
+        } else {
+            return Err(FormatError::new(
+                count_column,
+                FormatErrorKind::InvalidCount(count),
+            ));
+        }
+    }
+
+    let size_ok = match kind {
+        FieldKind::Signed | FieldKind::Unsigned => size == 1 || size == 2 || size == 4,
+        FieldKind::Float => size == 4,
+        FieldKind::String => true,
+    };
+    if !size_ok {
+        return Err(FormatError::new(column, FormatErrorKind::InvalidSize(size)));
+    }
+
+    Ok((size, kind, count))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let format = Format::parse("4s1 var1 2u1 var2 4f2 var3 1s0 label").unwrap();
+        assert_eq!(
+            format.fields,
+            vec![
+                Field {
+                    size: 4,
+                    kind: FieldKind::Signed,
+                    count: 1,
+                    name: String::from("var1"),
+                },
+                Field {
+                    size: 2,
+                    kind: FieldKind::Unsigned,
+                    count: 1,
+                    name: String::from("var2"),
+                },
+                Field {
+                    size: 4,
+                    kind: FieldKind::Float,
+                    count: 2,
+                    name: String::from("var3"),
+                },
+                Field {
+                    size: 1,
+                    kind: FieldKind::String,
+                    count: 0,
+                    name: String::from("label"),
+                },
+            ]
+        );
+        assert_eq!(format.byte_size(), None);
+        assert_eq!(format.field("var3").unwrap().byte_size(), Some(8));
+
+        let format: Format = "".parse().unwrap();
+        assert!(format.fields.is_empty());
+        assert_eq!(format.byte_size(), Some(0));
+    }
+
+    #[test]
+    fn render_test() {
+        let format = Format::parse("  4s1   var1\t2u1 var2 1s0 label ").unwrap();
+        assert_eq!(format.to_string(), "4s1 var1 2u1 var2 1s0 label");
+        assert_eq!(Format::parse(&format.to_string()).unwrap(), format);
+        assert_eq!(format.byte_size(), None);
+
+        let format = Format::parse("4u1 var1 2u1 var2 2u1 var3").unwrap();
+        assert_eq!(format.byte_size(), Some(8));
+    }
+
+    #[test]
+    fn error_test() {
+        let cases = [
+            ("u1 var1", 1, FormatErrorKind::MissingSize),
+            ("3s1 var1", 1, FormatErrorKind::InvalidSize(3)),
+            ("2f1 var1", 1, FormatErrorKind::InvalidSize(2)),
+            ("4 var1", 2, FormatErrorKind::MissingKind),
+            ("4x1 var1", 2, FormatErrorKind::InvalidKind('x')),
+            ("4s var1", 3, FormatErrorKind::MissingCount),
+            ("4s1x var1", 4, FormatErrorKind::MissingCount),
+            ("4u0 var1", 3, FormatErrorKind::InvalidCount(0)),
+            (
+                "4u4611686018427387904 x",
+                3,
+                FormatErrorKind::OutOfRange(String::from("4611686018427387904")),
+            ),
+            (
+                "4u99999999999999999999999 x",
+                3,
+                FormatErrorKind::OutOfRange(String::from("99999999999999999999999")),
+            ),
+            (
+                "99999999999999999999999u1 x",
+                1,
+                FormatErrorKind::OutOfRange(String::from("99999999999999999999999")),
+            ),
+            ("4s1 var1 2u1", 13, FormatErrorKind::MissingName),
+            (
+                "4s1 1var",
+                5,
+                FormatErrorKind::InvalidName(String::from("1var")),
+            ),
+            (
+                "4s1 var1 2u1 var2 2u1 var2",
+                23,
+                FormatErrorKind::DuplicateName(String::from("var2")),
+            ),
+        ];
+
+        for &(format, column, ref kind) in cases.iter() {
+            let err = Format::parse(format).err().unwrap();
+            assert_eq!(err.column, column, "{}", format);
+            assert_eq!(&err.kind, kind, "{}", format);
+        }
+
+        // Sizes stay in range for the largest counts that parse
+        let format = Format::parse("4u2147483647 a 4u2147483647 b 4u2147483647 c").unwrap();
+        assert!(format.byte_size().unwrap() >= 4 * 2147483647);
+
+        let err = Format::parse("4s1 var1 2u1 var1").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid format string at column 14: duplicate field name 'var1'"
+        );
+
+        let err = Format::parse("4u2147483648 x").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid format string at column 3: 2147483648 is out of range"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Chunked transfer of payloads that are too big for a single message.
+//!
+//! `SbioFragmenter` splits an event into fragment events that all keep the original target and
+//! name. Each fragment's format starts with [`FRAGMENT_FORMAT_PREFIX`] and its payload is a
+//! 12 byte header (transfer id, fragment index, fragment count) followed by a slice of the
+//! original format string, a nul, and the original payload. `SbioReassembler` collects the
+//! fragments on the receiving side, passes ordinary events straight through, and reports
+//! fragments that go missing or stop arriving.
+
+use sbio::error::SbioError;
+use std::time::{Duration, Instant};
+use SbioEvent;
+
+/// The start of the format string of every fragment event
+pub const FRAGMENT_FORMAT_PREFIX: &str = "4u1 sbio_transfer 4u1 sbio_index 4u1 sbio_count";
+
+/// The number of data bytes per fragment used by `SbioFragmenter::default`
+pub const DEFAULT_FRAGMENT_SIZE: usize = 4096;
+
+/// How long `SbioReassembler::default` waits for the next fragment of a transfer
+pub const DEFAULT_FRAGMENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The bytes of transfer id, fragment index and fragment count at the start of each fragment
+pub(crate) const FRAGMENT_HEADER_SIZE: usize = 12;
+
+/// The format string of a fragment carrying data_len bytes of the original event
+pub(crate) fn fragment_format(data_len: usize) -> String {
+    format!("{} 1u{} sbio_data", FRAGMENT_FORMAT_PREFIX, data_len)
+}
+
+/// Splits events into fragments of at most `fragment_size` data bytes
+#[derive(Clone, Debug)]
+pub struct SbioFragmenter {
+    fragment_size: usize,
+    next_transfer: u32,
+}
+
+impl SbioFragmenter {
+    /// `fragment_size` is the most data bytes per fragment. `send_fragmented` uses smaller
+    /// fragments when the channel reports a limit that this size wouldn't fit.
+    pub fn new(fragment_size: usize) -> SbioFragmenter {
+        SbioFragmenter {
+            fragment_size: fragment_size.max(1),
+            // Seeded with the process id so transfers from different senders don't collide
+            next_transfer: std::process::id() << 16,
+        }
+    }
+
+    pub fn fragment_size(&self) -> usize {
+        self.fragment_size
+    }
+
+    /// Split an event payload into `(format, payload)` pairs, one per fragment event
+    pub fn fragments(&mut self, format: &str, payload: &[u8]) -> Vec<(String, Vec<u8>)> {
+        self.fragments_of_size(format, payload, self.fragment_size)
+    }
+
+    /// Split an event payload using a smaller fragment size, such as one that fits the channel
+    pub(crate) fn fragments_of_size(
+        &mut self,
+        format: &str,
+        payload: &[u8],
+        fragment_size: usize,
+    ) -> Vec<(String, Vec<u8>)> {
+        let fragment_size = fragment_size.max(1);
+        let transfer = self.next_transfer;
+        self.next_transfer = self.next_transfer.wrapping_add(1);
+
+        let mut stream = Vec::with_capacity(format.len() + 1 + payload.len());
+        stream.extend_from_slice(format.as_bytes());
+        stream.push(0);
+        stream.extend_from_slice(payload);
+
+        let count = stream.len().div_ceil(fragment_size) as u32;
+        stream
+            .chunks(fragment_size)
+            .enumerate()
+            .map(|(index, data)| {
+                let format = fragment_format(data.len());
+                let mut fragment = Vec::with_capacity(FRAGMENT_HEADER_SIZE + data.len());
+                fragment.extend_from_slice(&transfer.to_ne_bytes());
+                fragment.extend_from_slice(&(index as u32).to_ne_bytes());
+                fragment.extend_from_slice(&count.to_ne_bytes());
+                fragment.extend_from_slice(data);
+                (format, fragment)
+            })
+            .collect()
+    }
+}
+
+impl Default for SbioFragmenter {
+    fn default() -> SbioFragmenter {
+        SbioFragmenter::new(DEFAULT_FRAGMENT_SIZE)
+    }
+}
+
+// A transfer that has received some of its fragments
+#[derive(Clone, Debug)]
+struct Partial {
+    target: String,
+    name: String,
+    transfer: u32,
+    count: u32,
+    received: u32,
+    data: Vec<u8>,
+    last_fragment: Instant,
+}
+
+/// Rebuilds fragmented events. Fragments of a transfer must arrive in order, which greio
+/// channels guarantee for a single sender.
+#[derive(Clone, Debug)]
+pub struct SbioReassembler {
+    timeout: Duration,
+    partials: Vec<Partial>,
+}
+
+impl SbioReassembler {
+    /// Give up on a transfer when its next fragment doesn't arrive within timeout
+    pub fn new(timeout: Duration) -> SbioReassembler {
+        SbioReassembler {
+            timeout,
+            partials: Vec::new(),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Whether any transfers are waiting for more fragments
+    pub fn is_pending(&self) -> bool {
+        !self.partials.is_empty()
+    }
+
+    /// Whether an event is a fragment produced by `SbioFragmenter`
+    pub fn is_fragment(event: &SbioEvent) -> bool {
+        event.format.starts_with(FRAGMENT_FORMAT_PREFIX)
+    }
+
+    /// Add a received event. Ordinary events are returned as is, fragments return `None` until
+    /// the last fragment of their transfer completes the original event. Stalled transfers
+    /// aren't checked here, so an event is never dropped for one; call `expire` to report them.
+    pub fn push(&mut self, event: SbioEvent) -> Result<Option<SbioEvent>, SbioError> {
+        if !SbioReassembler::is_fragment(&event) {
+            return Ok(Some(event));
+        }
+
+        let payload = &event.payload;
+        if payload.len() < FRAGMENT_HEADER_SIZE {
+            return Err(SbioError::PayloadSize {
+                expected: FRAGMENT_HEADER_SIZE,
+                actual: payload.len(),
+            });
+        }
+        let transfer = u32::from_ne_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let index = u32::from_ne_bytes([payload[4], payload[5], payload[6], payload[7]]);
+        let count = u32::from_ne_bytes([payload[8], payload[9], payload[10], payload[11]]);
+        let data = &payload[FRAGMENT_HEADER_SIZE..];
+        if index >= count {
+            return Err(SbioError::InvalidFragment { transfer });
+        }
+
+        let position = self.partials.iter().position(|partial| {
+            partial.transfer == transfer
+                && partial.target == event.target
+                && partial.name == event.name
+        });
+        let position = match position {
+            Some(position) => position,
+            None if index == 0 => {
+                self.partials.push(Partial {
+                    target: event.target.clone(),
+                    name: event.name.clone(),
+                    transfer,
+                    count,
+                    received: 0,
+                    data: Vec::new(),
+                    last_fragment: Instant::now(),
+                });
+                self.partials.len() - 1
+            }
+            None => {
+                return Err(SbioError::MissingFragment {
+                    transfer,
+                    expected: 0,
+                    received: index,
+                })
+            }
+        };
+
+        let partial = &mut self.partials[position];
+        if partial.count != count {
+            self.partials.remove(position);
+            return Err(SbioError::InvalidFragment { transfer });
+        }
+        if partial.received != index {
+            let expected = partial.received;
+            self.partials.remove(position);
+            return Err(SbioError::MissingFragment {
+                transfer,
+                expected,
+                received: index,
+            });
+        }
+
+        partial.data.extend_from_slice(data);
+        partial.received += 1;
+        partial.last_fragment = Instant::now();
+        if partial.received < partial.count {
+            return Ok(None);
+        }
+
+        let partial = self.partials.remove(position);
+        let format_len = match partial.data.iter().position(|&byte| byte == 0) {
+            Some(format_len) => format_len,
+            None => return Err(SbioError::InvalidFragment { transfer }),
+        };
+
+        Ok(Some(SbioEvent {
+            target: partial.target,
+            name: partial.name,
+            format: String::from_utf8_lossy(&partial.data[..format_len]).into_owned(),
+            payload: partial.data[format_len + 1..].to_vec(),
+        }))
+    }
+
+    /// Drop transfers whose next fragment is overdue, reporting the first one dropped
+    pub fn expire(&mut self) -> Result<(), SbioError> {
+        let timeout = self.timeout;
+        let expired = self
+            .partials
+            .iter()
+            .position(|partial| partial.last_fragment.elapsed() >= timeout);
+
+        match expired {
+            Some(position) => {
+                let partial = self.partials.remove(position);
+                Err(SbioError::FragmentTimeout {
+                    transfer: partial.transfer,
+                    received: partial.received,
+                    count: partial.count,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for SbioReassembler {
+    fn default() -> SbioReassembler {
+        SbioReassembler::new(DEFAULT_FRAGMENT_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment_events(
+        fragmenter: &mut SbioFragmenter,
+        format: &str,
+        payload: &[u8],
+    ) -> Vec<SbioEvent> {
+        fragmenter
+            .fragments(format, payload)
+            .into_iter()
+            .map(|(format, payload)| SbioEvent {
+                target: String::from("target"),
+                name: String::from("blob"),
+                format,
+                payload,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassemble_test() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut fragmenter = SbioFragmenter::new(16);
+        let fragments = fragment_events(&mut fragmenter, "1u100 data", &payload);
+        assert_eq!(fragments.len(), 7);
+        assert!(fragments
+            .iter()
+            .all(|fragment| fragment.payload.len() <= FRAGMENT_HEADER_SIZE + 16));
+
+        let mut reassembler = SbioReassembler::default();
+        let plain = SbioEvent {
+            name: String::from("plain"),
+            ..SbioEvent::default()
+        };
+        let last = fragments.len() - 1;
+        for (index, fragment) in fragments.into_iter().enumerate() {
+            let result = reassembler.push(fragment).unwrap();
+            if index == 3 {
+                // Ordinary events pass through while a transfer is in progress
+                assert_eq!(
+                    reassembler.push(plain.clone()).unwrap(),
+                    Some(plain.clone())
+                );
+            }
+            if index < last {
+                assert_eq!(result, None);
+                assert!(reassembler.is_pending());
+            } else {
+                let event = result.unwrap();
+                assert_eq!(event.target, "target");
+                assert_eq!(event.name, "blob");
+                assert_eq!(event.format, "1u100 data");
+                assert_eq!(event.payload, payload);
+            }
+        }
+        assert!(!reassembler.is_pending());
+    }
+
+    #[test]
+    fn missing_fragment_test() {
+        let payload = [7u8; 40];
+        let mut fragmenter = SbioFragmenter::new(8);
+        let mut fragments = fragment_events(&mut fragmenter, "1u40 data", &payload);
+        fragments.remove(2);
+
+        let mut reassembler = SbioReassembler::default();
+        let mut result = Ok(None);
+        for fragment in fragments {
+            result = reassembler.push(fragment);
+            if result.is_err() {
+                break;
+            }
+        }
+        match result {
+            Err(SbioError::MissingFragment {
+                expected, received, ..
+            }) => {
+                assert_eq!(expected, 2);
+                assert_eq!(received, 3);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // The rest of a dropped transfer is reported rather than silently restarted
+        let fragments = fragment_events(&mut fragmenter, "1u40 data", &payload);
+        let result = reassembler.push(fragments[1].clone());
+        assert!(matches!(
+            result,
+            Err(SbioError::MissingFragment { expected: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn fragment_timeout_test() {
+        let mut fragmenter = SbioFragmenter::new(8);
+        let fragments = fragment_events(&mut fragmenter, "1u40 data", &[1u8; 40]);
+
+        let mut reassembler = SbioReassembler::new(Duration::from_millis(10));
+        assert_eq!(reassembler.push(fragments[0].clone()).unwrap(), None);
+        std::thread::sleep(Duration::from_millis(20));
+
+        // An event that arrives after the transfer stalls is still delivered
+        let plain = SbioEvent {
+            name: String::from("plain"),
+            ..SbioEvent::default()
+        };
+        assert_eq!(reassembler.push(plain.clone()).unwrap(), Some(plain));
+        assert!(reassembler.is_pending());
+
+        let result = reassembler.expire();
+        assert!(matches!(
+            result,
+            Err(SbioError::FragmentTimeout { received: 1, .. })
+        ));
+        assert!(!reassembler.is_pending());
+        assert_eq!(reassembler.expire(), Ok(()));
+    }
+}
@@ -0,0 +1,208 @@
+//! Decoder for received multi data events.
+//!
+//! `gre_io_send_mdata` sends a single `greio.mdata_set` event whose payload is a sequence of
+//! records. Each record starts with a `gre_io_mdata_t` header (record size, data offset, data
+//! size and format spec), followed by the nul terminated key name and then the data, which starts
+//! `data_offset` bytes after the end of the header.
+
+use sbio::error::SbioError;
+use sbio::format::Format;
+use sbio::sbio_sys::gre_io_mdata_t;
+use sbio::value::{SbioValue, SbioValueMap};
+use std::borrow::Cow;
+use std::mem::size_of;
+
+/// The event name used for multi data events
+pub const MDATA_EVENT_NAME: &str = "greio.mdata_set";
+
+const HEADER_SIZE: usize = size_of::<gre_io_mdata_t>();
+const FORMAT_SIZE: usize = 8;
+
+/// A single key/value entry of a multi data event
+#[derive(Clone, Debug, PartialEq)]
+pub struct SbioMultiDataEntry<'a> {
+    key: &'a [u8],
+    format: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> SbioMultiDataEntry<'a> {
+    /// The data manager key, with any invalid UTF-8 replaced
+    pub fn key(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.key)
+    }
+
+    /// The format spec of the value, e.g. `"4u1"`
+    pub fn format(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.format)
+    }
+
+    /// The raw value bytes
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decode the value as described by its format spec
+    pub fn value(&self) -> Result<SbioValue, SbioError> {
+        let format = Format::parse(&format!("{} value", self.format()))?;
+        let values = SbioValueMap::decode(&format, self.data)?;
+        match values.into_iter().next() {
+            Some((_, value)) => Ok(value),
+            None => Err(SbioError::MissingField(String::from("value"))),
+        }
+    }
+}
+
+/// Iterator over the entries of a multi data payload. Every offset is checked against the
+/// payload, and iteration stops after the first malformed record.
+#[derive(Clone, Debug)]
+pub struct SbioMultiDataEntries<'a> {
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SbioMultiDataEntries<'a> {
+    pub fn new(payload: &'a [u8]) -> SbioMultiDataEntries<'a> {
+        SbioMultiDataEntries { payload, offset: 0 }
+    }
+
+    fn malformed(&mut self) -> Option<Result<SbioMultiDataEntry<'a>, SbioError>> {
+        let offset = self.offset;
+        self.offset = self.payload.len();
+        Some(Err(SbioError::InvalidMultiData { offset }))
+    }
+}
+
+impl<'a> Iterator for SbioMultiDataEntries<'a> {
+    type Item = Result<SbioMultiDataEntry<'a>, SbioError>;
+
+    fn next(&mut self) -> Option<Result<SbioMultiDataEntry<'a>, SbioError>> {
+        let rest = &self.payload[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        if rest.len() < HEADER_SIZE {
+            return self.malformed();
+        }
+
+        let size = u16::from_ne_bytes([rest[0], rest[1]]) as usize;
+        let data_offset = u16::from_ne_bytes([rest[2], rest[3]]) as usize;
+        let data_size = u32::from_ne_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let format = &rest[8..8 + FORMAT_SIZE];
+
+        if size < HEADER_SIZE || size > rest.len() {
+            return self.malformed();
+        }
+        let record = &rest[HEADER_SIZE..size];
+        let data_end = match data_offset.checked_add(data_size) {
+            Some(data_end) if data_end <= record.len() => data_end,
+            _ => return self.malformed(),
+        };
+
+        let key = &record[..data_offset];
+        let key = match key.iter().position(|&byte| byte == 0) {
+            Some(end) => &key[..end],
+            None => return self.malformed(),
+        };
+        let format = match format.iter().position(|&byte| byte == 0) {
+            Some(end) => &format[..end],
+            None => format,
+        };
+
+        self.offset += size;
+        Some(Ok(SbioMultiDataEntry {
+            key,
+            format,
+            data: &record[data_offset..data_end],
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &str, format: &str, data: &[u8]) -> Vec<u8> {
+        let data_offset = (key.len() + 1).next_multiple_of(8);
+        let size = (HEADER_SIZE + data_offset + data.len()).next_multiple_of(8);
+        let mut record = Vec::with_capacity(size);
+        record.extend_from_slice(&(size as u16).to_ne_bytes());
+        record.extend_from_slice(&(data_offset as u16).to_ne_bytes());
+        record.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        let mut format_bytes = [0u8; FORMAT_SIZE];
+        format_bytes[..format.len()].copy_from_slice(format.as_bytes());
+        record.extend_from_slice(&format_bytes);
+        record.extend_from_slice(key.as_bytes());
+        record.resize(HEADER_SIZE + data_offset, 0);
+        record.extend_from_slice(data);
+        record.resize(size, 0);
+        record
+    }
+
+    #[test]
+    fn entries_test() {
+        let mut payload = record("app.speed", "4u1", &20u32.to_ne_bytes());
+        payload.extend(record("app.label", "1s0", b"Crank\0"));
+        payload.extend(record("app.pos", "2s2", &[255, 255, 2, 0]));
+
+        let entries: Vec<SbioMultiDataEntry> = SbioMultiDataEntries::new(&payload)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].key(), "app.speed");
+        assert_eq!(entries[0].format(), "4u1");
+        assert_eq!(entries[0].value().unwrap(), SbioValue::U32(20));
+        assert_eq!(
+            entries[1].value().unwrap(),
+            SbioValue::String(String::from("Crank"))
+        );
+        assert_eq!(
+            entries[2].value().unwrap(),
+            SbioValue::Array(vec![SbioValue::I16(-1), SbioValue::I16(2)])
+        );
+    }
+
+    #[test]
+    fn malformed_test() {
+        let good = record("app.speed", "4u1", &20u32.to_ne_bytes());
+
+        // Truncated header
+        let mut entries = SbioMultiDataEntries::new(&good[..8]);
+        assert_eq!(
+            entries.next(),
+            Some(Err(SbioError::InvalidMultiData { offset: 0 }))
+        );
+        assert_eq!(entries.next(), None);
+
+        // Record size past the end of the payload
+        let mut payload = good.clone();
+        payload.extend_from_slice(&good[..HEADER_SIZE]);
+        let mut entries = SbioMultiDataEntries::new(&payload);
+        assert!(entries.next().unwrap().is_ok());
+        assert_eq!(
+            entries.next(),
+            Some(Err(SbioError::InvalidMultiData { offset: good.len() }))
+        );
+        assert_eq!(entries.next(), None);
+
+        // Record size smaller than the header
+        let mut bad = good.clone();
+        bad[0..2].copy_from_slice(&0u16.to_ne_bytes());
+        assert!(SbioMultiDataEntries::new(&bad).next().unwrap().is_err());
+
+        // Data past the end of the record
+        let mut bad = good.clone();
+        bad[4..8].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert!(SbioMultiDataEntries::new(&bad).next().unwrap().is_err());
+
+        // Key without a terminator
+        let mut bad = good.clone();
+        bad[2..4].copy_from_slice(&4u16.to_ne_bytes());
+        assert!(SbioMultiDataEntries::new(&bad).next().unwrap().is_err());
+
+        // Value shorter than its format
+        let bad = record("app.speed", "4u1", &[1, 2]);
+        let entry = SbioMultiDataEntries::new(&bad).next().unwrap().unwrap();
+        assert!(entry.value().is_err());
+    }
+}
@@ -1 +1,10 @@
+pub mod channel;
+pub mod error;
+pub mod format;
+pub mod fragment;
+pub mod mdata;
+pub mod payload;
 pub mod sbio_sys;
+#[cfg(feature = "tokio")]
+pub mod stream;
+pub mod value;
@@ -0,0 +1,23 @@
+use sbio::error::SbioError;
+
+/// A Rust type with a fixed Storyboard event layout.
+///
+/// This is normally implemented with `#[derive(SbioEvent)]` (enabled by the `derive` feature),
+/// which fills in the format string and byte size from the struct's fields and reads the event
+/// name and target from an optional `#[sbio(name = "...", target = "...")]` attribute.
+pub trait SbioPayload: Sized {
+    /// The Storyboard format string describing the payload
+    const FORMAT: &'static str;
+    /// The number of bytes in the encoded payload
+    const SIZE: usize;
+    /// The event name used when sending the value
+    const NAME: &'static str;
+    /// The event target used when sending the value, empty for the default target
+    const TARGET: &'static str;
+
+    /// Encode the value into a payload of `SIZE` bytes laid out as described by `FORMAT`
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode a value from a payload laid out as described by `FORMAT`
+    fn decode(payload: &[u8]) -> Result<Self, SbioError>;
+}
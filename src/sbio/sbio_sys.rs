@@ -8,6 +8,7 @@ extern crate libc;
 use self::bitflags::bitflags;
 
 use self::libc::c_char;
+use sbio::error::SbioError;
 use std::ffi::c_void;
 use std::ffi::CStr;
 use std::ffi::CString;
@@ -15,7 +16,7 @@ use std::ffi::CString;
 include!("./bindings.rs");
 
 bitflags! {
-    #[derive(PartialEq)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     pub struct SBIO_FLAGS: u32 {
         const RDONLY = GRE_IO_TYPE_RDONLY;
         const XRDONLY = GRE_IO_TYPE_XRDONLY;
@@ -41,17 +42,38 @@ pub struct sbio_serialized_data {
     pub size: i32,
 }
 
+unsafe impl Send for sbio_serialized_data {}
+
+/// Copy a string argument for passing to greio, rejecting interior nul bytes
+fn c_string(value: &str, argument: &'static str) -> Result<CString, SbioError> {
+    CString::new(value).map_err(|_| SbioError::InvalidString { argument })
+}
+
+/// Convert a byte count to the int greio takes, rejecting counts it can't represent
+fn c_len(len: usize) -> Result<i32, SbioError> {
+    if len > i32::MAX as usize {
+        Err(SbioError::MessageTooLarge {
+            size: len,
+            max: i32::MAX as usize,
+        })
+    } else {
+        Ok(len as i32)
+    }
+}
+
 /// Open a SBIO channel using a named connection
-pub fn open(channel_name: &str, flags: SBIO_FLAGS) -> Result<sbio_channel_handle, &'static str> {
-    let name_cstr = CString::new(channel_name).unwrap().into_raw();
+pub fn open(channel_name: &str, flags: SBIO_FLAGS) -> Result<sbio_channel_handle, SbioError> {
+    let name = c_string(channel_name, "channel_name")?;
     let handle: *mut gre_io_t;
     unsafe {
-        handle = gre_io_open(name_cstr, flags.as_u32() as i32);
-        drop(CString::from_raw(name_cstr));
+        handle = gre_io_open(name.as_ptr(), flags.as_u32() as i32);
     }
 
     if handle.is_null() {
-        Err("Couldn't open SBIO channel")
+        Err(SbioError::from_handle(
+            "Couldn't open SBIO channel",
+            std::ptr::null_mut(),
+        ))
     } else {
         Ok(sbio_channel_handle {
             channel_handle: handle,
@@ -77,187 +99,290 @@ pub fn serialize<T>(
     format: &str,
     data: T,
     size: u32,
-) -> Result<sbio_serialized_data, &'static str> {
-    let buffer: *mut gre_io_serialized_data_t;
-
-    unsafe {
-        let target_ptr: *mut c_char = CString::new(target).unwrap().into_raw();
-        let name_ptr: *mut c_char = CString::new(name).unwrap().into_raw();
-        let format_ptr: *mut c_char = CString::new(format).unwrap().into_raw();
-        let ptr: *const T = &data;
-        let data_in = ptr as *const c_void;
-        //let data_in = &data as *const _ as *const c_void;
-
-        buffer = gre_io_serialize(
-            std::ptr::null_mut(),
-            target_ptr,
-            name_ptr,
-            format_ptr,
-            data_in,
-            size as i32,
-        );
+) -> Result<sbio_serialized_data, SbioError> {
+    let mut buffer = new_buffer();
+    serialize_into(&mut buffer, target, name, format, data, size)?;
+    Ok(buffer)
+}
 
-        drop(CString::from_raw(target_ptr));
-        drop(CString::from_raw(name_ptr));
-        drop(CString::from_raw(format_ptr));
+/// Serialize SBIO event into an existing buffer, growing it if needed
+pub fn serialize_into<T>(
+    buffer: &mut sbio_serialized_data,
+    target: &str,
+    name: &str,
+    format: &str,
+    data: T,
+    size: u32,
+) -> Result<(), SbioError> {
+    // gre_io_serialize copies size bytes from data, so it must not read past the value
+    if size as usize > std::mem::size_of::<T>() {
+        return Err(SbioError::SizeMismatch {
+            size: size as usize,
+            data_size: std::mem::size_of::<T>(),
+        });
     }
 
-    if buffer.is_null() {
-        Err("Couldn't serialize event data")
-    } else {
-        Ok(sbio_serialized_data {
+    // T may have padding, so its bytes go to greio as a raw pointer rather than a slice
+    let target = c_string(target, "target")?;
+    let name = c_string(name, "name")?;
+    let format = c_string(format, "format")?;
+    let data_nbytes = c_len(size as usize)?;
+    unsafe {
+        serialize_raw_into(
             buffer,
-            size: size as i32,
-        })
+            &target,
+            &name,
+            &format,
+            &data as *const T as *const c_void,
+            data_nbytes,
+        )
     }
 }
 
-/// Unserialize SBIO event
-pub fn unserialize<'a, T>(buffer: &sbio_serialized_data) -> (&'a str, &'a str, &'a str, &T, i32) {
-    let target: &str;
-    let name: &str;
-    let format: &str;
-    let data: &T;
-    let size;
+/// Serialize SBIO event from an already encoded payload
+pub fn serialize_bytes(
+    target: &str,
+    name: &str,
+    format: &str,
+    data: &[u8],
+) -> Result<sbio_serialized_data, SbioError> {
+    let mut buffer = new_buffer();
+    serialize_bytes_into(&mut buffer, target, name, format, data)?;
+    Ok(buffer)
+}
 
-    unsafe {
-        let mut target_ptr: *mut c_char = std::ptr::null_mut();
-        let mut name_ptr: *mut c_char = std::ptr::null_mut();
-        let mut format_ptr: *mut c_char = std::ptr::null_mut();
-        let mut data_ptr: *mut c_void = std::ptr::null_mut();
+/// Serialize SBIO event from an already encoded payload into an existing buffer, growing it if
+/// needed. The buffer is left untouched if serializing fails.
+pub fn serialize_bytes_into(
+    buffer: &mut sbio_serialized_data,
+    target: &str,
+    name: &str,
+    format: &str,
+    data: &[u8],
+) -> Result<(), SbioError> {
+    let target = c_string(target, "target")?;
+    let name = c_string(name, "name")?;
+    let format = c_string(format, "format")?;
+    serialize_cstr_into(buffer, &target, &name, &format, data)
+}
 
-        size = gre_io_unserialize(
-            buffer.buffer,
-            &mut target_ptr as *mut *mut c_char,
-            &mut name_ptr as *mut *mut c_char,
-            &mut format_ptr as *mut *mut c_char,
-            &mut data_ptr as *mut *mut c_void,
-        );
+/// Serialize SBIO event from strings that are passed to greio as is, so they don't need to be
+/// valid UTF-8
+pub fn serialize_cstr_into(
+    buffer: &mut sbio_serialized_data,
+    target: &CStr,
+    name: &CStr,
+    format: &CStr,
+    data: &[u8],
+) -> Result<(), SbioError> {
+    let data_nbytes = c_len(data.len())?;
+    unsafe {
+        serialize_raw_into(
+            buffer,
+            target,
+            name,
+            format,
+            data.as_ptr() as *const c_void,
+            data_nbytes,
+        )
+    }
+}
 
-        target = CStr::from_ptr(target_ptr).to_str().unwrap();
-        name = CStr::from_ptr(name_ptr).to_str().unwrap();
-        format = CStr::from_ptr(format_ptr).to_str().unwrap();
-        let ptr = data_ptr as *const T;
-        data = &*ptr;
+/// Serialize data_nbytes read from data, which must be valid for reads of that many bytes
+unsafe fn serialize_raw_into(
+    buffer: &mut sbio_serialized_data,
+    target: &CStr,
+    name: &CStr,
+    format: &CStr,
+    data: *const c_void,
+    data_nbytes: i32,
+) -> Result<(), SbioError> {
+    let new_buffer = gre_io_serialize(
+        buffer.buffer,
+        target.as_ptr(),
+        name.as_ptr(),
+        format.as_ptr(),
+        data,
+        data_nbytes,
+    );
+
+    if new_buffer.is_null() {
+        Err(SbioError::from_errno("Couldn't serialize event data"))
+    } else {
+        buffer.buffer = new_buffer;
+        buffer.size = data_nbytes;
+        Ok(())
     }
+}
 
-    (target, name, format, data, size)
+/// Where each part of a serialized event lies in its buffer, found with a single
+/// `gre_io_unserialize` call. Parts are kept as offsets into the buffer data rather than
+/// pointers, so a header can move between threads with its buffer and outlives a reallocation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct sbio_event_header {
+    target: Option<usize>,
+    name: Option<usize>,
+    format: Option<usize>,
+    payload: Option<usize>,
+    payload_size: usize,
 }
 
-pub fn unserialize_event_name(buffer: &sbio_serialized_data) -> &str {
-    let _target: &str;
-    let name: &str;
-    let _format: &str;
-    let _size;
+impl sbio_event_header {
+    /// The event target
+    pub fn target<'a>(&self, buffer: &'a sbio_serialized_data) -> &'a CStr {
+        header_str(buffer, self.target)
+    }
 
-    unsafe {
-        let mut target_ptr: *mut c_char = std::ptr::null_mut();
-        let mut name_ptr: *mut c_char = std::ptr::null_mut();
-        let mut format_ptr: *mut c_char = std::ptr::null_mut();
-        let mut data_ptr: *mut c_void = std::ptr::null_mut();
+    /// The event name
+    pub fn name<'a>(&self, buffer: &'a sbio_serialized_data) -> &'a CStr {
+        header_str(buffer, self.name)
+    }
 
-        _size = gre_io_unserialize(
-            buffer.buffer,
-            &mut target_ptr as *mut *mut c_char,
-            &mut name_ptr as *mut *mut c_char,
-            &mut format_ptr as *mut *mut c_char,
-            &mut data_ptr as *mut *mut c_void,
-        );
+    /// The event format string
+    pub fn format<'a>(&self, buffer: &'a sbio_serialized_data) -> &'a CStr {
+        header_str(buffer, self.format)
+    }
 
-        _target = CStr::from_ptr(target_ptr).to_str().unwrap();
-        name = CStr::from_ptr(name_ptr).to_str().unwrap();
-        _format = CStr::from_ptr(format_ptr).to_str().unwrap();
+    /// The raw event payload bytes
+    pub fn payload<'a>(&self, buffer: &'a sbio_serialized_data) -> &'a [u8] {
+        match self.payload {
+            Some(offset) => {
+                let data = buffer_data(buffer);
+                let end = data.len().min(offset + self.payload_size);
+                data.get(offset..end).unwrap_or_default()
+            }
+            None => &[],
+        }
     }
+}
 
-    name
+/// Read a string at offset in the buffer data, treating a missing or unterminated string as empty
+fn header_str(buffer: &sbio_serialized_data, offset: Option<usize>) -> &CStr {
+    offset
+        .and_then(|offset| buffer_data(buffer).get(offset..))
+        .and_then(|bytes| CStr::from_bytes_until_nul(bytes).ok())
+        .unwrap_or_default()
 }
 
-pub fn unserialize_event_target(buffer: &sbio_serialized_data) -> &str {
-    let target: &str;
-    let _name: &str;
-    let _format: &str;
-    let _size;
+/// The serialized data held by a buffer
+fn buffer_data(buffer: &sbio_serialized_data) -> &[u8] {
+    let len = buffer_len(buffer);
+    if len == 0 {
+        return &[];
+    }
 
     unsafe {
-        let mut target_ptr: *mut c_char = std::ptr::null_mut();
-        let mut name_ptr: *mut c_char = std::ptr::null_mut();
-        let mut format_ptr: *mut c_char = std::ptr::null_mut();
-        let mut data_ptr: *mut c_void = std::ptr::null_mut();
-
-        _size = gre_io_unserialize(
-            buffer.buffer,
-            &mut target_ptr as *mut *mut c_char,
-            &mut name_ptr as *mut *mut c_char,
-            &mut format_ptr as *mut *mut c_char,
-            &mut data_ptr as *mut *mut c_void,
-        );
-
-        target = CStr::from_ptr(target_ptr).to_str().unwrap();
-        _name = CStr::from_ptr(name_ptr).to_str().unwrap();
-        _format = CStr::from_ptr(format_ptr).to_str().unwrap();
+        let data = (*buffer.buffer).buffer;
+        if data.is_null() {
+            return &[];
+        }
+        std::slice::from_raw_parts(data as *const u8, len)
     }
-
-    target
 }
 
-pub fn unserialize_event_format(buffer: &sbio_serialized_data) -> &str {
-    let _target: &str;
-    let _name: &str;
-    let format: &str;
-    let _size;
+/// Locate the target, name, format and payload of a serialized event in one pass
+pub fn unserialize_header(buffer: &sbio_serialized_data) -> sbio_event_header {
+    let data = buffer_data(buffer);
+    if data.is_empty() {
+        return Default::default();
+    }
 
-    unsafe {
-        let mut target_ptr: *mut c_char = std::ptr::null_mut();
-        let mut name_ptr: *mut c_char = std::ptr::null_mut();
-        let mut format_ptr: *mut c_char = std::ptr::null_mut();
-        let mut data_ptr: *mut c_void = std::ptr::null_mut();
+    let mut target_ptr: *mut c_char = std::ptr::null_mut();
+    let mut name_ptr: *mut c_char = std::ptr::null_mut();
+    let mut format_ptr: *mut c_char = std::ptr::null_mut();
+    let mut data_ptr: *mut c_void = std::ptr::null_mut();
+    let size;
 
-        _size = gre_io_unserialize(
+    unsafe {
+        size = gre_io_unserialize(
             buffer.buffer,
             &mut target_ptr as *mut *mut c_char,
             &mut name_ptr as *mut *mut c_char,
             &mut format_ptr as *mut *mut c_char,
             &mut data_ptr as *mut *mut c_void,
         );
+    }
 
-        _target = CStr::from_ptr(target_ptr).to_str().unwrap();
-        _name = CStr::from_ptr(name_ptr).to_str().unwrap();
-        format = CStr::from_ptr(format_ptr).to_str().unwrap();
+    // Anything greio points outside of the buffer data is ignored rather than trusted
+    let start = data.as_ptr() as usize;
+    let offset = |ptr: *const c_void| {
+        let ptr = ptr as usize;
+        if ptr >= start && ptr < start + data.len() {
+            Some(ptr - start)
+        } else {
+            None
+        }
+    };
+
+    let payload = if size > 0 { offset(data_ptr) } else { None };
+    sbio_event_header {
+        target: offset(target_ptr as *const c_void),
+        name: offset(name_ptr as *const c_void),
+        format: offset(format_ptr as *const c_void),
+        payload,
+        payload_size: size.max(0) as usize,
     }
+}
 
-    format
+/// An empty buffer, allocated by the first call that needs it
+pub fn new_buffer() -> sbio_serialized_data {
+    sbio_serialized_data {
+        buffer: std::ptr::null_mut(),
+        size: 0,
+    }
 }
 
-pub fn unserialize_event_size(buffer: &sbio_serialized_data) -> i32 {
-    let _target: &str;
-    let _name: &str;
-    let _format: &str;
-    let size;
+/// Make sure a buffer can hold a message of nbytes, allocating it if needed. Existing content is
+/// kept, and the buffer is left untouched if resizing fails.
+pub fn size_buffer(buffer: &mut sbio_serialized_data, nbytes: usize) -> Result<(), SbioError> {
+    let nbytes = c_len(nbytes)?;
+    let new_buffer: *mut gre_io_serialized_data_t;
+    unsafe { new_buffer = gre_io_size_buffer(buffer.buffer, nbytes) }
+
+    if new_buffer.is_null() {
+        Err(SbioError::from_errno("Couldn't size buffer"))
+    } else {
+        buffer.buffer = new_buffer;
+        Ok(())
+    }
+}
+
+/// Discard the content of a buffer, keeping its allocation
+pub fn zero_buffer(buffer: &mut sbio_serialized_data) {
+    if buffer.buffer.is_null() {
+        return;
+    }
 
     unsafe {
-        let mut target_ptr: *mut c_char = std::ptr::null_mut();
-        let mut name_ptr: *mut c_char = std::ptr::null_mut();
-        let mut format_ptr: *mut c_char = std::ptr::null_mut();
-        let mut data_ptr: *mut c_void = std::ptr::null_mut();
+        gre_io_zero_buffer(buffer.buffer);
+    }
+    buffer.size = 0;
+}
 
-        size = gre_io_unserialize(
-            buffer.buffer,
-            &mut target_ptr as *mut *mut c_char,
-            &mut name_ptr as *mut *mut c_char,
-            &mut format_ptr as *mut *mut c_char,
-            &mut data_ptr as *mut *mut c_void,
-        );
+/// The number of bytes allocated for a buffer
+pub fn buffer_capacity(buffer: &sbio_serialized_data) -> usize {
+    if buffer.buffer.is_null() {
+        return 0;
+    }
 
-        _target = CStr::from_ptr(target_ptr).to_str().unwrap();
-        _name = CStr::from_ptr(name_ptr).to_str().unwrap();
-       _format = CStr::from_ptr(format_ptr).to_str().unwrap();
+    unsafe { (*buffer.buffer).buffer_nbytes.max(0) as usize }
+}
+
+/// The number of bytes of serialized data held by a buffer
+pub fn buffer_len(buffer: &sbio_serialized_data) -> usize {
+    if buffer.buffer.is_null() {
+        return 0;
     }
 
-    size
+    unsafe { (*buffer.buffer).data_nbytes.max(0) as usize }
 }
 
 /// Free serialized data
 pub fn free_buffer(buffer: &sbio_serialized_data) {
+    if buffer.buffer.is_null() {
+        return;
+    }
+
     unsafe {
         gre_io_free_buffer(buffer.buffer);
     }
@@ -267,19 +392,22 @@ pub fn free_buffer(buffer: &sbio_serialized_data) {
 pub fn send(
     channel_handle: &sbio_channel_handle,
     event: &sbio_serialized_data,
-) -> Result<i32, &'static str> {
+) -> Result<i32, SbioError> {
     let ret: i32;
     unsafe { ret = gre_io_send(channel_handle.channel_handle, event.buffer) }
 
     if ret == -1 {
-        Err("Couldn't send event")
+        Err(SbioError::from_handle(
+            "Couldn't send event",
+            channel_handle.channel_handle,
+        ))
     } else {
         Ok(ret)
     }
 }
 
 /// Receive an event
-pub fn receive(channel_handle: &sbio_channel_handle) -> Result<sbio_serialized_data, &'static str> {
+pub fn receive(channel_handle: &sbio_channel_handle) -> Result<sbio_serialized_data, SbioError> {
     let mut buffer: *mut gre_io_serialized_data_t = std::ptr::null_mut();
     let ret: i32;
 
@@ -291,15 +419,253 @@ pub fn receive(channel_handle: &sbio_channel_handle) -> Result<sbio_serialized_d
     }
 
     if ret == -1 {
-        Err("Couldn't receive event")
+        Err(SbioError::from_handle(
+            "Couldn't receive event",
+            channel_handle.channel_handle,
+        ))
     } else {
         Ok(sbio_serialized_data { buffer, size: ret })
     }
 }
 
+/// Add a data manager key/value pair to a multi data buffer
+pub fn add_mdata(
+    mbuffer: &mut sbio_serialized_data,
+    key: &str,
+    format: &str,
+    data: &[u8],
+) -> Result<(), SbioError> {
+    let key = c_string(key, "key")?;
+    let format = c_string(format, "format")?;
+    let data_nbytes = c_len(data.len())?;
+    let ret: i32;
+
+    unsafe {
+        ret = gre_io_add_mdata(
+            &mut mbuffer.buffer as *mut *mut gre_io_serialized_data_t,
+            key.as_ptr(),
+            format.as_ptr(),
+            data.as_ptr() as *const c_void,
+            data_nbytes,
+        );
+    }
+
+    if ret == -1 {
+        Err(SbioError::from_errno("Couldn't add multi data entry"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Send a multi data buffer
+pub fn send_mdata(
+    channel_handle: &sbio_channel_handle,
+    mbuffer: &sbio_serialized_data,
+) -> Result<i32, SbioError> {
+    let ret: i32;
+    unsafe { ret = gre_io_send_mdata(channel_handle.channel_handle, mbuffer.buffer) }
+
+    if ret == -1 {
+        Err(SbioError::from_handle(
+            "Couldn't send multi data",
+            channel_handle.channel_handle,
+        ))
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Receive an event into an existing buffer, growing it if needed
+pub fn receive_into(
+    channel_handle: &sbio_channel_handle,
+    buffer: &mut sbio_serialized_data,
+) -> Result<i32, SbioError> {
+    let ret: i32;
+
+    unsafe {
+        ret = gre_io_receive(
+            channel_handle.channel_handle,
+            &mut buffer.buffer as *mut *mut gre_io_serialized_data_t,
+        );
+    }
+
+    if ret == -1 {
+        Err(SbioError::from_handle(
+            "Couldn't receive event",
+            channel_handle.channel_handle,
+        ))
+    } else {
+        buffer.size = ret;
+        Ok(ret)
+    }
+}
+
+/// The largest message that can be sent over the channel, or `None` if the channel is unlimited
+/// or the greio implementation doesn't report a limit
+pub fn max_message(channel_handle: &sbio_channel_handle) -> Option<usize> {
+    let ret: i32;
+    unsafe { ret = gre_io_max_message(channel_handle.channel_handle) }
+
+    message_limit(ret)
+}
+
+/// Map a gre_io_max_message result, where -1 is an error and GRE_IO_MAX_MSG_UNLIMITED no limit
+fn message_limit(ret: i32) -> Option<usize> {
+    if ret > GRE_IO_MAX_MSG_UNLIMITED as i32 {
+        Some(ret as usize)
+    } else {
+        None
+    }
+}
+
+/// Raise the channel's message size limit to fit the buffer's capacity
+pub fn grow_buffer(
+    channel_handle: &sbio_channel_handle,
+    buffer: &sbio_serialized_data,
+) -> Result<(), SbioError> {
+    let ret: i32;
+    unsafe { ret = gre_io_grow_buffer(channel_handle.channel_handle, buffer.buffer) }
+
+    if ret == -1 {
+        // Implementations without support fail without recording a cause
+        if !SbioError::has_cause(channel_handle.channel_handle) {
+            return Err(SbioError::Unsupported(
+                "the greio implementation can't grow the channel message size",
+            ));
+        }
+        Err(SbioError::from_handle(
+            "Couldn't grow channel message size",
+            channel_handle.channel_handle,
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Take ownership of a malloc'd format string and event data block returned by the greio
+/// string conversion functions
+unsafe fn take_event_data(
+    format_ptr: *mut c_char,
+    data_ptr: *mut c_void,
+    data_nbytes: i32,
+) -> (String, Vec<u8>) {
+    let format = CStr::from_ptr(format_ptr)
+        .to_string_lossy()
+        .trim_end()
+        .to_string();
+    let data = if data_ptr.is_null() || data_nbytes <= 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data_ptr as *const u8, data_nbytes as usize).to_vec()
+    };
+
+    libc::free(format_ptr as *mut c_void);
+    libc::free(data_ptr);
+
+    (format, data)
+}
+
+/// Convert an event payload to the parsable data string used by greio_string_to_event_data,
+/// e.g. `4u1:value 20 1s0:name "Crank"`
+pub fn event_data_to_string(format: &str, data: &[u8]) -> Result<String, SbioError> {
+    // greio returns NULL for empty events, which isn't an error
+    if format.trim().is_empty() || data.is_empty() {
+        return Ok(String::new());
+    }
+
+    let format_ptr: *mut c_char = c_string(format, "format")?.into_raw();
+    let data_nbytes = c_len(data.len())?;
+    let string_ptr: *mut c_char;
+
+    unsafe {
+        string_ptr =
+            greio_event_data_to_string(format_ptr, data.as_ptr() as *mut c_void, data_nbytes);
+        drop(CString::from_raw(format_ptr));
+    }
+
+    if string_ptr.is_null() {
+        return Err(SbioError::from_errno(
+            "Couldn't convert event data to a string",
+        ));
+    }
+
+    let string;
+    unsafe {
+        string = CStr::from_ptr(string_ptr)
+            .to_string_lossy()
+            .trim_end()
+            .to_string();
+        libc::free(string_ptr as *mut c_void);
+    }
+
+    Ok(string)
+}
+
+/// Parse a data string such as `4u1:value 20 1s0:name Crank` into a format string and payload
+pub fn string_to_event_data(data_string: &str) -> Result<(String, Vec<u8>), SbioError> {
+    // greio tokenizes the string in place, so it gets its own copy
+    let string_ptr: *mut c_char = c_string(data_string, "data_string")?.into_raw();
+    let mut data_ptr: *mut c_void = std::ptr::null_mut();
+    let mut data_nbytes: i32 = 0;
+    let format_ptr: *mut c_char;
+
+    unsafe {
+        format_ptr = greio_string_to_event_data(
+            string_ptr,
+            &mut data_ptr as *mut *mut c_void,
+            &mut data_nbytes as *mut i32,
+        );
+        drop(CString::from_raw(string_ptr));
+    }
+
+    if format_ptr.is_null() {
+        return Err(SbioError::from_errno(
+            "Couldn't convert string to event data",
+        ));
+    }
+
+    unsafe { Ok(take_event_data(format_ptr, data_ptr, data_nbytes)) }
+}
+
+/// Parse argv style segments such as `["4u1:value", "20", "1s0:name", "Crank"]` into a format
+/// string and payload
+pub fn array_to_event_data(args: &[&str]) -> Result<(String, Vec<u8>), SbioError> {
+    // greio splits the type segments at ':' in place, so each argument gets its own mutable copy
+    let mut arg_strings: Vec<Vec<u8>> = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        arg_strings.push(c_string(arg, "args")?.into_bytes_with_nul());
+    }
+    let mut arg_ptrs: Vec<*mut c_char> = arg_strings
+        .iter_mut()
+        .map(|arg| arg.as_mut_ptr() as *mut c_char)
+        .collect();
+    let argc = c_len(arg_ptrs.len())?;
+    let mut data_ptr: *mut c_void = std::ptr::null_mut();
+    let mut data_nbytes: i32 = 0;
+    let format_ptr: *mut c_char;
+
+    unsafe {
+        format_ptr = greio_array_to_event_data(
+            arg_ptrs.as_mut_ptr(),
+            argc,
+            &mut data_ptr as *mut *mut c_void,
+            &mut data_nbytes as *mut i32,
+        );
+    }
+
+    if format_ptr.is_null() {
+        return Err(SbioError::from_errno(
+            "Couldn't convert arguments to event data",
+        ));
+    }
+
+    unsafe { Ok(take_event_data(format_ptr, data_ptr, data_nbytes)) }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sbio::error::SbioErrorCode;
 
     #[derive(PartialEq, Debug, Copy, Clone)]
     struct TestData {
@@ -316,11 +682,34 @@ mod tests {
         close(&handle);
     }
 
+    #[test]
+    fn receive_empty_error_test() {
+        let result = open(
+            "receive_empty_error",
+            SBIO_FLAGS::RDONLY | SBIO_FLAGS::NONBLOCK,
+        );
+        let handle = result.unwrap();
+
+        let result = receive(&handle);
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert_eq!(err.code(), Some(SbioErrorCode::QueueEmpty));
+
+        close(&handle);
+    }
+
+    #[test]
+    fn message_limit_test() {
+        assert_eq!(message_limit(-1), None);
+        assert_eq!(message_limit(GRE_IO_MAX_MSG_UNLIMITED as i32), None);
+        assert_eq!(message_limit(4096), Some(4096));
+    }
+
     #[test]
     fn serialize_test() {
         let target_in = "target";
         let name_in = "event1";
-        let format_in = "4s1 var1 2u1 var2 2u1 var2";
+        let format_in = "4s1 var1 2u1 var2 2u1 var3";
         let data_in = TestData {
             var1: 100,
             var2: 10,
@@ -332,28 +721,58 @@ mod tests {
         assert!(result.is_ok());
         let buffer = result.unwrap();
 
-        let target_out: &str;
-        let name_out: &str;
-        let format_out: &str;
-        let ptr: &TestData;
-        let size_out: i32;
-        (target_out, name_out, format_out, ptr, size_out) = unserialize(&buffer);
-        assert_eq!(size_out, size_in as i32);
-        assert_eq!(target_out, target_out);
-        assert_eq!(name_out, name_in);
-        assert_eq!(format_out, format_in);
-        assert_eq!(ptr.var1, data_in.var1);
-        assert_eq!(ptr.var2, data_in.var2);
-        assert_eq!(ptr.var3, data_in.var3);
+        let header = unserialize_header(&buffer);
+        assert_eq!(header.target(&buffer).to_bytes(), target_in.as_bytes());
+        assert_eq!(header.name(&buffer).to_bytes(), name_in.as_bytes());
+        assert_eq!(header.format(&buffer).to_bytes(), format_in.as_bytes());
+
+        let mut payload_in = Vec::new();
+        payload_in.extend_from_slice(&data_in.var1.to_ne_bytes());
+        payload_in.extend_from_slice(&data_in.var2.to_ne_bytes());
+        payload_in.extend_from_slice(&data_in.var3.to_ne_bytes());
+        assert_eq!(header.payload(&buffer), &payload_in[..]);
+
+        let empty = new_buffer();
+        let header = unserialize_header(&empty);
+        assert_eq!(header, sbio_event_header::default());
+        assert!(header.name(&empty).is_empty());
+        assert!(header.payload(&empty).is_empty());
 
         free_buffer(&buffer);
     }
 
+    #[test]
+    fn event_data_string_test() {
+        let mut data_in: Vec<u8> = Vec::new();
+        data_in.extend_from_slice(&20u32.to_ne_bytes());
+        data_in.extend_from_slice(b"Crank\0");
+
+        let result = event_data_to_string("4u1 value 1s0 name", &data_in);
+        let string = result.unwrap();
+        assert_eq!(string, "4u1:value 20 1s0:name \"Crank\"");
+
+        let result = string_to_event_data("4u1:value 20 1s0:name Crank");
+        let (format, data) = result.unwrap();
+        assert_eq!(format, "4u1 value 1s0 name");
+        assert_eq!(data, data_in);
+
+        let result = array_to_event_data(&["4u1:value", "20", "1s0:name", "Crank"]);
+        let (format, data) = result.unwrap();
+        assert_eq!(format, "4u1 value 1s0 name");
+        assert_eq!(data, data_in);
+
+        let result = event_data_to_string("", &[]);
+        assert_eq!(result.unwrap(), "");
+
+        let result = string_to_event_data("4u1:value\x00 20");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn send_receive_test() {
         let target_in = "target";
         let name_in = "event1";
-        let format_in = "4s1 var1 2u1 var2 2u1 var2";
+        let format_in = "4s1 var1 2u1 var2 2u1 var3";
         let data_in = TestData {
             var1: 100,
             var2: 10,
@@ -0,0 +1,549 @@
+//! Async adapters for tokio, enabled with the `tokio` feature.
+//!
+//! `SbioReceiver` is a `Stream` of received events. The first poll starts the receiver's
+//! listener thread, which copies each event out of its greio buffer and hands it to the polling
+//! task through a bounded channel, waking the task as it does. Polling is cancellation safe: an
+//! event that arrives after a `next()` future is dropped stays queued for the next poll. A receive
+//! error is yielded once and then ends the stream, as it ends the listener.
+//!
+//! Shutting the stream down with `stop_listener`, `close` or by dropping the receiver drops the
+//! channel before joining the listener thread, so it never waits on a task that has stopped
+//! polling. Events already handed to the channel at that point are discarded.
+//!
+//! `SbioSender` is a `Sink` of owned events, which needs a sender opened with
+//! `SBIO_FLAGS::NONBLOCK`. `start_send` queues the event for a dedicated send thread, so a full
+//! channel never blocks the task: while the channel is full the thread retries with a backoff,
+//! `poll_ready` waits for room in the sink's own queue and `poll_flush` for the queue to drain.
+//! Once `start_send` returns, the event is sent even if the task stops polling. Closing the sink
+//! with `poll_close` delivers what's queued and then stops the thread. Dropping or closing the
+//! sender stops the thread within one retry interval and discards anything still queued, so flush
+//! first to deliver it.
+
+extern crate futures_core;
+extern crate futures_sink;
+extern crate libc;
+extern crate tokio;
+
+use self::futures_core::Stream;
+use self::futures_sink::Sink;
+use self::tokio::sync::mpsc;
+use sbio::error::{SbioError, SbioErrorCode};
+use sbio::sbio_sys::SBIO_FLAGS;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{ready, Context, Poll, Waker};
+use std::thread::{sleep, Builder, JoinHandle};
+use {send_checked, RECEIVE_POLL_MAX, RECEIVE_POLL_MIN};
+use {SbioBuffer, SbioConnection, SbioConnectionData, SbioEvent, SbioReceiver, SbioSender};
+
+/// The number of received events buffered for the polling task before the listener thread waits
+const STREAM_CAPACITY: usize = 64;
+
+/// The number of events the sink queues for its send thread before `poll_ready` waits
+const SINK_CAPACITY: usize = 64;
+
+/// How far a receiver's stream has got
+#[derive(Debug, Default)]
+pub(crate) enum SbioStreamState {
+    /// Not polled yet, or stopped with `stop_listener`
+    #[default]
+    Idle,
+    /// The listener thread is running and sending events to the channel
+    Listening(mpsc::Receiver<SbioEvent>),
+    /// The listener couldn't start or stopped with an error that has been yielded
+    Ended,
+}
+
+/// Start the listener thread, sending each event it receives to the returned channel
+fn start_stream(connection: &mut SbioConnection) -> Result<mpsc::Receiver<SbioEvent>, SbioError> {
+    let (sender, events) = mpsc::channel(STREAM_CAPACITY);
+    connection.on_event(move |event| {
+        // This only fails once the stream has been dropped, and the listener is stopped next
+        let _ = sender.blocking_send(event.into_owned());
+    })?;
+
+    Ok(events)
+}
+
+impl SbioReceiver {
+    /// Drop the stream's channel so a listener thread waiting on a full channel can exit
+    pub(crate) fn stop_stream(&mut self) {
+        self.stream = SbioStreamState::Idle;
+    }
+}
+
+/// Events received on the channel. The channel must be non-blocking, a receiver opened without
+/// `SBIO_FLAGS::NONBLOCK` yields `Unsupported` and ends.
+impl Stream for SbioReceiver {
+    type Item = Result<SbioEvent, SbioError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let receiver = self.get_mut();
+        if let SbioStreamState::Idle = receiver.stream {
+            match start_stream(&mut receiver.connection) {
+                Ok(events) => receiver.stream = SbioStreamState::Listening(events),
+                Err(err) => {
+                    receiver.stream = SbioStreamState::Ended;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+
+        let events = match receiver.stream {
+            SbioStreamState::Listening(ref mut events) => events,
+            _ => return Poll::Ready(None),
+        };
+        match events.poll_recv(cx) {
+            Poll::Ready(Some(event)) => Poll::Ready(Some(Ok(event))),
+            Poll::Ready(None) => {
+                // The listener thread has exited, collect the error that stopped it
+                receiver.stream = SbioStreamState::Ended;
+                match receiver.connection.stop_listener() {
+                    Ok(()) => Poll::Ready(None),
+                    Err(err) => Poll::Ready(Some(Err(err))),
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// State shared between a sink and its send thread
+#[derive(Default)]
+struct SinkQueue {
+    events: VecDeque<SbioEvent>,
+    // The send thread has taken an event off the queue and not finished sending it
+    sending: bool,
+    // A send failure not yet reported to the task
+    error: Option<SbioError>,
+    stopping: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct SinkShared {
+    queue: Mutex<SinkQueue>,
+    // Signalled when an event is queued or the thread should stop
+    queued: Condvar,
+}
+
+impl SinkShared {
+    fn lock(&self) -> MutexGuard<'_, SinkQueue> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A sender's sink, started by its first use
+#[derive(Default)]
+pub(crate) struct SbioSinkState {
+    shared: Arc<SinkShared>,
+    thread: Option<JoinHandle<()>>,
+    // Set once poll_close completes, after which the sink takes no more events
+    closed: bool,
+}
+
+impl SbioSinkState {
+    fn stop(&mut self) {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return,
+        };
+
+        self.shared.lock().stopping = true;
+        self.shared.queued.notify_all();
+        let _ = thread.join();
+        self.shared = Default::default();
+    }
+}
+
+impl Drop for SbioSinkState {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Send loop run on the sink thread until the sink is stopped
+fn send_queued(
+    shared: Arc<SinkShared>,
+    thread_data: Arc<Mutex<SbioConnectionData>>,
+    max_message: Option<usize>,
+) {
+    let mut buffer = SbioBuffer::new();
+    loop {
+        let event = {
+            let mut queue = shared.lock();
+            loop {
+                if queue.stopping {
+                    return;
+                }
+                if let Some(event) = queue.events.pop_front() {
+                    queue.sending = true;
+                    break event;
+                }
+                queue = shared
+                    .queued
+                    .wait(queue)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+        };
+
+        let result = send_retrying(&shared, &thread_data, max_message, &mut buffer, &event);
+        let mut queue = shared.lock();
+        queue.sending = false;
+        if let Err(err) = result {
+            queue.error = Some(err);
+        }
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Send an event, retrying with a backoff while the channel is full. Gives up without an error
+/// if the sink is stopped meanwhile.
+fn send_retrying(
+    shared: &SinkShared,
+    thread_data: &Mutex<SbioConnectionData>,
+    max_message: Option<usize>,
+    buffer: &mut SbioBuffer,
+    event: &SbioEvent,
+) -> Result<(), SbioError> {
+    buffer.serialize_bytes(&event.target, &event.name, &event.format, &event.payload)?;
+
+    let mut interval = RECEIVE_POLL_MIN;
+    loop {
+        match send_checked(thread_data, max_message, &buffer.buffer) {
+            Ok(_) => return Ok(()),
+            Err(ref err) if is_queue_full(err) => {}
+            Err(err) => return Err(err),
+        }
+
+        if shared.lock().stopping {
+            return Ok(());
+        }
+        sleep(interval);
+        interval = std::cmp::min(interval * 2, RECEIVE_POLL_MAX);
+    }
+}
+
+// Not every greio implementation reports QueueFull, the SysV queue build only leaves EAGAIN
+fn is_queue_full(err: &SbioError) -> bool {
+    err.code() == Some(SbioErrorCode::QueueFull) || err.sys_code() == Some(libc::EAGAIN)
+}
+
+impl SbioSender {
+    /// Stop the sink's send thread, discarding any events it hasn't sent
+    pub(crate) fn stop_sink(&mut self) {
+        self.sink.stop();
+    }
+
+    fn start_sink(&mut self) -> Result<(), SbioError> {
+        if self.sink.closed {
+            return Err(SbioError::Closed("sink"));
+        }
+        if self.sink.thread.is_some() {
+            return Ok(());
+        }
+        if self.connection.flags & SBIO_FLAGS::NONBLOCK.as_u32() == 0 {
+            return Err(SbioError::Unsupported(
+                "the sink requires a non-blocking sender",
+            ));
+        }
+
+        let shared = Arc::clone(&self.sink.shared);
+        let thread_data = Arc::clone(&self.connection.thread_data);
+        let max_message = self.connection.max_message;
+        let spawned = Builder::new()
+            .name(String::from("sbio-sink"))
+            .spawn(move || send_queued(shared, thread_data, max_message));
+        match spawned {
+            Ok(thread) => self.sink.thread = Some(thread),
+            Err(err) => return Err(SbioError::from_io("Couldn't start sink thread", err)),
+        }
+
+        Ok(())
+    }
+
+    // Wait until the queue has room, or until it's empty and nothing is being sent
+    fn poll_sink(&mut self, cx: &mut Context<'_>, drained: bool) -> Poll<Result<(), SbioError>> {
+        self.start_sink()?;
+
+        let mut queue = self.sink.shared.lock();
+        if let Some(err) = queue.error.take() {
+            return Poll::Ready(Err(err));
+        }
+        let ready = if drained {
+            queue.events.is_empty() && !queue.sending
+        } else {
+            queue.events.len() < SINK_CAPACITY
+        };
+        if ready {
+            Poll::Ready(Ok(()))
+        } else {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Sends owned events on a dedicated thread. A sender opened without `SBIO_FLAGS::NONBLOCK`
+/// fails with `Unsupported`.
+impl Sink<SbioEvent> for SbioSender {
+    type Error = SbioError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SbioError>> {
+        self.get_mut().poll_sink(cx, false)
+    }
+
+    fn start_send(self: Pin<&mut Self>, event: SbioEvent) -> Result<(), SbioError> {
+        let sender = self.get_mut();
+        sender.start_sink()?;
+
+        sender.sink.shared.lock().events.push_back(event);
+        sender.sink.shared.queued.notify_one();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SbioError>> {
+        self.get_mut().poll_sink(cx, true)
+    }
+
+    /// Wait for queued events to be sent, then stop the send thread. The sink takes no more
+    /// events once closed, though the sender's own send methods still work.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SbioError>> {
+        let sender = self.get_mut();
+        if sender.sink.closed {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(sender.poll_sink(cx, true))?;
+
+        sender.stop_sink();
+        sender.sink.closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+    use std::time::{Duration, Instant};
+    use Sbio;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Poll until ready, parking the test thread until the waker is called
+    fn block_on<T, F>(mut poll: F) -> T
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<T>,
+    {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Poll::Ready(value) = poll(&mut cx) {
+                return value;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting to be woken");
+            thread::park_timeout(Duration::from_millis(100));
+        }
+    }
+
+    fn test_event(value: u32) -> SbioEvent {
+        SbioEvent {
+            target: String::from("target"),
+            name: String::from("stream_event"),
+            format: String::from("4u1 value"),
+            payload: value.to_ne_bytes().to_vec(),
+        }
+    }
+
+    fn nonblocking_sender(name: &str) -> SbioSender {
+        let mut sbio = Sbio();
+        sbio.connection(name)
+            .unwrap()
+            .write()
+            .nonblocking(true)
+            .sender()
+            .unwrap()
+    }
+
+    fn large_event(value: u8) -> SbioEvent {
+        SbioEvent {
+            target: String::from("target"),
+            name: String::from("large_event"),
+            format: String::from("1u1000 data"),
+            payload: vec![value; 1000],
+        }
+    }
+
+    #[test]
+    fn stream_sink_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("stream_sink_test").unwrap();
+        let mut send = nonblocking_sender("stream_sink_test");
+
+        // Nothing is queued yet, so the first poll starts the listener and waits
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut rcv).poll_next(&mut cx).is_pending());
+        assert!(rcv.is_listening());
+
+        for value in 0..3 {
+            let mut sink = Pin::new(&mut send);
+            assert!(sink.as_mut().poll_ready(&mut cx).is_ready());
+            sink.start_send(test_event(value)).unwrap();
+        }
+        let result = block_on(|cx| Pin::new(&mut send).poll_flush(cx));
+        assert_eq!(result, Ok(()));
+
+        for value in 0..3 {
+            let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+            assert_eq!(event.unwrap().unwrap(), test_event(value));
+        }
+
+        // Closing delivers what's queued, stops the send thread and refuses further events
+        Pin::new(&mut send).start_send(test_event(3)).unwrap();
+        let result = block_on(|cx| Pin::new(&mut send).poll_close(cx));
+        assert_eq!(result, Ok(()));
+        assert!(send.sink.thread.is_none());
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        assert_eq!(event.unwrap().unwrap(), test_event(3));
+        assert_eq!(
+            Pin::new(&mut send).start_send(test_event(4)),
+            Err(SbioError::Closed("sink"))
+        );
+        let result = Pin::new(&mut send).poll_close(&mut cx);
+        assert_eq!(result, Poll::Ready(Ok(())));
+
+        // The sender itself can still send, until the channel is closed
+        send.send_owned_event(&test_event(5)).unwrap();
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        assert_eq!(event.unwrap().unwrap(), test_event(5));
+        send.close();
+        assert_eq!(
+            send.send_owned_event(&test_event(6)),
+            Err(SbioError::Closed("channel"))
+        );
+    }
+
+    #[test]
+    fn stream_shutdown_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("stream_shutdown_test").unwrap();
+        let mut send = sbio.connect_send("stream_shutdown_test").unwrap();
+
+        // Queue more events than the stream buffers, so the listener ends up waiting on the task
+        let count = STREAM_CAPACITY as u32 + 8;
+        for value in 0..count {
+            send.send_owned_event(&test_event(value)).unwrap();
+        }
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        assert_eq!(event.unwrap().unwrap(), test_event(0));
+        thread::sleep(Duration::from_millis(50));
+
+        // Stopping doesn't wait for the stream to be drained, and the next poll listens again
+        rcv.stop_listener().unwrap();
+        assert!(!rcv.is_listening());
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        assert!(event.unwrap().is_ok());
+        assert!(rcv.is_listening());
+    }
+
+    #[test]
+    fn sink_full_queue_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio.connect_receive("sink_full_queue_test").unwrap();
+        let mut send = nonblocking_sender("sink_full_queue_test");
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        // Nothing reads the channel, so it fills up and then the sink's own queue does. No poll
+        // waits for the channel.
+        let start = Instant::now();
+        let mut sent = 0;
+        while Pin::new(&mut send).poll_ready(&mut cx).is_ready() {
+            Pin::new(&mut send).start_send(large_event(1)).unwrap();
+            sent += 1;
+            assert!(sent <= 1000, "the sink never filled up");
+        }
+        assert!(sent >= SINK_CAPACITY);
+        assert!(Pin::new(&mut send).poll_flush(&mut cx).is_pending());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // Reading the channel lets the sink drain
+        let mut received = 0;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            while let Some(event) = rcv.try_receive().unwrap() {
+                assert_eq!(event.payload(), &[1; 1000][..]);
+                received += 1;
+            }
+            if Pin::new(&mut send).poll_flush(&mut cx).is_ready() {
+                break;
+            }
+            assert!(Instant::now() < deadline, "the sink never drained");
+            thread::park_timeout(Duration::from_millis(1));
+        }
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while received < sent && Instant::now() < deadline {
+            match rcv.try_receive().unwrap() {
+                Some(_) => received += 1,
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        assert_eq!(received, sent);
+
+        // Dropping a sender with a full channel doesn't wait for room
+        for _ in 0..SINK_CAPACITY {
+            Pin::new(&mut send).start_send(large_event(2)).unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        drop(send);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sink_blocking_test() {
+        let mut sbio = Sbio();
+        let _rcv = sbio.connect_receive("sink_blocking_test").unwrap();
+        let mut send = sbio.connect_send("sink_blocking_test").unwrap();
+
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let result = Pin::new(&mut send).poll_ready(&mut cx);
+        assert!(matches!(
+            result,
+            Poll::Ready(Err(SbioError::Unsupported(_)))
+        ));
+    }
+
+    #[test]
+    fn stream_blocking_test() {
+        let mut sbio = Sbio();
+        let mut rcv = sbio
+            .connection("stream_blocking_test")
+            .unwrap()
+            .read()
+            .receiver()
+            .unwrap();
+
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        let err = event.unwrap().unwrap_err();
+        assert!(matches!(err, SbioError::Unsupported(_)));
+        let event = block_on(|cx| Pin::new(&mut rcv).poll_next(cx));
+        assert!(event.is_none());
+    }
+}
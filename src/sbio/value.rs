@@ -0,0 +1,453 @@
+use sbio::error::SbioError;
+use sbio::format::{Field, FieldKind, Format};
+use std::slice;
+use std::vec;
+
+/// A dynamically typed field value
+#[derive(Clone, Debug, PartialEq)]
+pub enum SbioValue {
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    F32(f32),
+    String(String),
+    /// The elements of an array field. Decoding gives one for a count greater than one, encoding
+    /// also accepts a one element array for a count of one.
+    Array(Vec<SbioValue>),
+}
+
+impl SbioValue {
+    /// The format field describing the value, named `value`. Array elements must all have the
+    /// same scalar type.
+    pub fn field(&self) -> Result<Field, SbioError> {
+        let (size, kind, count) = match self {
+            SbioValue::I8(_) => (1, FieldKind::Signed, 1),
+            SbioValue::I16(_) => (2, FieldKind::Signed, 1),
+            SbioValue::I32(_) => (4, FieldKind::Signed, 1),
+            SbioValue::U8(_) => (1, FieldKind::Unsigned, 1),
+            SbioValue::U16(_) => (2, FieldKind::Unsigned, 1),
+            SbioValue::U32(_) => (4, FieldKind::Unsigned, 1),
+            SbioValue::F32(_) => (4, FieldKind::Float, 1),
+            SbioValue::String(_) => (1, FieldKind::String, 0),
+            SbioValue::Array(elements) => {
+                let element = match elements.first() {
+                    Some(SbioValue::Array(_)) | Some(SbioValue::String(_)) | None => {
+                        return Err(SbioError::InvalidValue {
+                            field: String::from("value"),
+                            spec: String::from("array"),
+                        })
+                    }
+                    Some(element) => element.field()?,
+                };
+                (element.size, element.kind, elements.len())
+            }
+        };
+
+        Ok(Field {
+            size,
+            kind,
+            count,
+            name: String::from("value"),
+        })
+    }
+
+    /// Encode the value on its own, returning its format spec (e.g. `"4u1"`) and payload
+    pub fn encode_single(&self) -> Result<(String, Vec<u8>), SbioError> {
+        let field = self.field()?;
+        let spec = field.spec();
+        let format = Format {
+            fields: vec![field],
+        };
+        let values: SbioValueMap = vec![("value", self.clone())].into_iter().collect();
+
+        Ok((spec, values.encode(&format)?))
+    }
+}
+
+/// Field values keyed by name, kept in insertion order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SbioValueMap {
+    entries: Vec<(String, SbioValue)>,
+}
+
+impl SbioValueMap {
+    pub fn new() -> SbioValueMap {
+        SbioValueMap::default()
+    }
+
+    /// Set a value, replacing any existing value for the same name in place
+    pub fn insert(&mut self, name: &str, value: SbioValue) -> Option<SbioValue> {
+        for entry in self.entries.iter_mut() {
+            if entry.0 == name {
+                return Some(std::mem::replace(&mut entry.1, value));
+            }
+        }
+        self.entries.push((String::from(name), value));
+        None
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SbioValue> {
+        self.entries
+            .iter()
+            .find(|entry| entry.0 == name)
+            .map(|entry| &entry.1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, (String, SbioValue)> {
+        self.entries.iter()
+    }
+
+    /// Decode a payload laid out as described by format
+    pub fn decode(format: &Format, payload: &[u8]) -> Result<SbioValueMap, SbioError> {
+        let mut values = SbioValueMap::new();
+        let mut offset = 0;
+
+        for field in format.fields.iter() {
+            let value = match field.kind {
+                FieldKind::String => decode_string(payload, &mut offset)?,
+                _ if field.count == 1 => decode_scalar(field, payload, &mut offset)?,
+                _ => {
+                    // The count comes from the sender, so check it against the payload before
+                    // allocating for it
+                    check_array_size(field, payload, offset)?;
+                    let mut elements = Vec::with_capacity(field.count);
+                    for _ in 0..field.count {
+                        elements.push(decode_scalar(field, payload, &mut offset)?);
+                    }
+                    SbioValue::Array(elements)
+                }
+            };
+            values.insert(&field.name, value);
+        }
+
+        Ok(values)
+    }
+
+    /// Encode the values into a payload laid out as described by format. Every field in the
+    /// format needs a value of the matching type, values for names not in the format are ignored.
+    pub fn encode(&self, format: &Format) -> Result<Vec<u8>, SbioError> {
+        let mut payload = Vec::new();
+
+        for field in format.fields.iter() {
+            let value = match self.get(&field.name) {
+                Some(value) => value,
+                None => return Err(SbioError::MissingField(field.name.clone())),
+            };
+
+            match (field.kind, value) {
+                (FieldKind::String, SbioValue::String(string)) => {
+                    if string.as_bytes().contains(&0) {
+                        return Err(invalid_value(field));
+                    }
+                    payload.extend_from_slice(string.as_bytes());
+                    payload.push(0);
+                }
+                (kind, SbioValue::Array(elements)) if kind != FieldKind::String => {
+                    if elements.len() != field.count {
+                        return Err(invalid_value(field));
+                    }
+                    for element in elements.iter() {
+                        encode_scalar(field, element, &mut payload)?;
+                    }
+                }
+                _ if field.count == 1 => encode_scalar(field, value, &mut payload)?,
+                _ => return Err(invalid_value(field)),
+            }
+        }
+
+        Ok(payload)
+    }
+}
+
+impl IntoIterator for SbioValueMap {
+    type Item = (String, SbioValue);
+    type IntoIter = vec::IntoIter<(String, SbioValue)>;
+
+    fn into_iter(self) -> vec::IntoIter<(String, SbioValue)> {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SbioValueMap {
+    type Item = &'a (String, SbioValue);
+    type IntoIter = slice::Iter<'a, (String, SbioValue)>;
+
+    fn into_iter(self) -> slice::Iter<'a, (String, SbioValue)> {
+        self.entries.iter()
+    }
+}
+
+impl<S: Into<String>> std::iter::FromIterator<(S, SbioValue)> for SbioValueMap {
+    fn from_iter<I: IntoIterator<Item = (S, SbioValue)>>(iter: I) -> SbioValueMap {
+        let mut values = SbioValueMap::new();
+        for (name, value) in iter {
+            values.insert(&name.into(), value);
+        }
+        values
+    }
+}
+
+fn invalid_value(field: &Field) -> SbioError {
+    SbioError::InvalidValue {
+        field: field.name.clone(),
+        spec: field.spec(),
+    }
+}
+
+// Take the next size bytes of the payload
+fn take<'a>(payload: &'a [u8], offset: &mut usize, size: usize) -> Result<&'a [u8], SbioError> {
+    let end = *offset + size;
+    if end > payload.len() {
+        return Err(SbioError::PayloadSize {
+            expected: end,
+            actual: payload.len(),
+        });
+    }
+    let bytes = &payload[*offset..end];
+    *offset = end;
+    Ok(bytes)
+}
+
+fn check_array_size(field: &Field, payload: &[u8], offset: usize) -> Result<(), SbioError> {
+    let end = field
+        .size
+        .checked_mul(field.count)
+        .and_then(|size| size.checked_add(offset));
+    match end {
+        Some(end) if end <= payload.len() => Ok(()),
+        _ => Err(SbioError::PayloadSize {
+            expected: end.unwrap_or(usize::MAX),
+            actual: payload.len(),
+        }),
+    }
+}
+
+fn decode_scalar(
+    field: &Field,
+    payload: &[u8],
+    offset: &mut usize,
+) -> Result<SbioValue, SbioError> {
+    let bytes = take(payload, offset, field.size)?;
+    let value = match (field.kind, field.size) {
+        (FieldKind::Signed, 1) => SbioValue::I8(bytes[0] as i8),
+        (FieldKind::Unsigned, 1) => SbioValue::U8(bytes[0]),
+        (FieldKind::Signed, 2) => SbioValue::I16(i16::from_ne_bytes([bytes[0], bytes[1]])),
+        (FieldKind::Unsigned, 2) => SbioValue::U16(u16::from_ne_bytes([bytes[0], bytes[1]])),
+        (FieldKind::Signed, 4) => {
+            SbioValue::I32(i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+        (FieldKind::Unsigned, 4) => {
+            SbioValue::U32(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+        (FieldKind::Float, 4) => {
+            SbioValue::F32(f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+        _ => return Err(invalid_value(field)),
+    };
+    Ok(value)
+}
+
+// Strings are nul terminated, a missing terminator runs to the end of the payload
+fn decode_string(payload: &[u8], offset: &mut usize) -> Result<SbioValue, SbioError> {
+    let rest = &payload[*offset..];
+    let (bytes, consumed) = match rest.iter().position(|&byte| byte == 0) {
+        Some(end) => (&rest[..end], end + 1),
+        None => (rest, rest.len()),
+    };
+    *offset += consumed;
+    Ok(SbioValue::String(
+        String::from_utf8_lossy(bytes).into_owned(),
+    ))
+}
+
+fn encode_scalar(field: &Field, value: &SbioValue, payload: &mut Vec<u8>) -> Result<(), SbioError> {
+    match (field.kind, field.size, value) {
+        (FieldKind::Signed, 1, SbioValue::I8(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        (FieldKind::Unsigned, 1, SbioValue::U8(value)) => payload.push(*value),
+        (FieldKind::Signed, 2, SbioValue::I16(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        (FieldKind::Unsigned, 2, SbioValue::U16(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        (FieldKind::Signed, 4, SbioValue::I32(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        (FieldKind::Unsigned, 4, SbioValue::U32(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        (FieldKind::Float, 4, SbioValue::F32(value)) => {
+            payload.extend_from_slice(&value.to_ne_bytes())
+        }
+        _ => return Err(invalid_value(field)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_test() {
+        let format = Format::parse("2s1 a 1u3 b 4f1 c 1s0 d").unwrap();
+        let payload = [251, 255, 1, 2, 3, 0, 0, 192, 63, 104, 105, 0];
+
+        let values = SbioValueMap::decode(&format, &payload).unwrap();
+        let names: Vec<&str> = values.iter().map(|entry| entry.0.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+        assert_eq!(values.get("a"), Some(&SbioValue::I16(-5)));
+        assert_eq!(
+            values.get("b"),
+            Some(&SbioValue::Array(vec![
+                SbioValue::U8(1),
+                SbioValue::U8(2),
+                SbioValue::U8(3)
+            ]))
+        );
+        assert_eq!(values.get("c"), Some(&SbioValue::F32(1.5)));
+        assert_eq!(
+            values.get("d"),
+            Some(&SbioValue::String(String::from("hi")))
+        );
+
+        let result = SbioValueMap::decode(&format, &payload[..4]);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::PayloadSize {
+                expected: 5,
+                actual: 4
+            })
+        );
+    }
+
+    #[test]
+    fn huge_count_test() {
+        let payload = 20u32.to_ne_bytes();
+
+        let format = Format::parse("1u2147483647 x").unwrap();
+        let result = SbioValueMap::decode(&format, &payload);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::PayloadSize {
+                expected: 2147483647,
+                actual: 4
+            })
+        );
+
+        // A count whose byte size overflows
+        let format = Format {
+            fields: vec![Field {
+                size: 4,
+                kind: FieldKind::Unsigned,
+                count: usize::MAX,
+                name: String::from("x"),
+            }],
+        };
+        let result = SbioValueMap::decode(&format, &payload);
+        assert_eq!(
+            result.err(),
+            Some(SbioError::PayloadSize {
+                expected: usize::MAX,
+                actual: 4
+            })
+        );
+    }
+
+    #[test]
+    fn encode_single_test() {
+        assert_eq!(
+            SbioValue::U32(20).encode_single().unwrap(),
+            (String::from("4u1"), 20u32.to_ne_bytes().to_vec())
+        );
+        assert_eq!(
+            SbioValue::String(String::from("hi"))
+                .encode_single()
+                .unwrap(),
+            (String::from("1s0"), b"hi\0".to_vec())
+        );
+
+        let array = SbioValue::Array(vec![SbioValue::I16(-1), SbioValue::I16(2)]);
+        let (spec, payload) = array.encode_single().unwrap();
+        assert_eq!(spec, "2s2");
+        assert_eq!(payload.len(), 4);
+
+        let single = SbioValue::Array(vec![SbioValue::I8(-1)]);
+        assert_eq!(
+            single.encode_single().unwrap(),
+            (String::from("1s1"), vec![255])
+        );
+
+        let mixed = SbioValue::Array(vec![SbioValue::I16(-1), SbioValue::U8(2)]);
+        assert!(mixed.encode_single().is_err());
+        assert!(SbioValue::Array(Vec::new()).encode_single().is_err());
+    }
+
+    #[test]
+    fn encode_test() {
+        let format = Format::parse("4u1 value 2s2 pos 1s0 name").unwrap();
+        let mut values: SbioValueMap = vec![
+            ("name", SbioValue::String(String::from("Crank"))),
+            ("value", SbioValue::U32(20)),
+            (
+                "pos",
+                SbioValue::Array(vec![SbioValue::I16(-1), SbioValue::I16(2)]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let payload = values.encode(&format).unwrap();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&20u32.to_ne_bytes());
+        expected.extend_from_slice(&(-1i16).to_ne_bytes());
+        expected.extend_from_slice(&2i16.to_ne_bytes());
+        expected.extend_from_slice(b"Crank\0");
+        assert_eq!(payload, expected);
+
+        let decoded = SbioValueMap::decode(&format, &payload).unwrap();
+        let names: Vec<&str> = decoded.iter().map(|entry| entry.0.as_str()).collect();
+        assert_eq!(names, vec!["value", "pos", "name"]);
+        assert_eq!(decoded.get("pos"), values.get("pos"));
+
+        values.insert("value", SbioValue::I32(20));
+        assert_eq!(
+            values.encode(&format).err(),
+            Some(SbioError::InvalidValue {
+                field: String::from("value"),
+                spec: String::from("4u1"),
+            })
+        );
+
+        values.insert("value", SbioValue::Array(vec![SbioValue::U32(20)]));
+        assert_eq!(values.encode(&format).unwrap(), expected);
+
+        values.insert("name", SbioValue::Array(Vec::new()));
+        assert_eq!(
+            values.encode(&format).err(),
+            Some(SbioError::InvalidValue {
+                field: String::from("name"),
+                spec: String::from("1s0"),
+            })
+        );
+
+        let format = Format::parse("4u1 missing").unwrap();
+        assert_eq!(
+            values.encode(&format).err(),
+            Some(SbioError::MissingField(String::from("missing")))
+        );
+    }
+}